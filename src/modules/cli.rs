@@ -1,27 +1,37 @@
 use anyhow::{Result, anyhow};
 
-use clap::{ArgMatches, Command, value_parser, command, ColorChoice, arg};
+use clap::{Arg, ArgAction, ArgMatches, Command, value_parser, command, ColorChoice, arg};
 use clap::builder::styling::{Styles, AnsiColor};
 
 use rfd::AsyncFileDialog;
 use serde_yml::Value;
 use tokio::fs::create_dir;
 use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
 
 use log::{error, info};
 use indoc::indoc;
 
 use crate::modules::utils::{create_file, 
     file_contents, 
-    handle_logged_result
+    ensure_dir,
+    handle_logged_result,
+    parse_duration,
+    sanitize_string
 };
 use crate::modules::task::{
     execute_tasks,
+    plan_tasks,
+    PlannedTask,
+    RunOptions,
+    FailurePolicy,
     get_task_str_sequence,
-    task_defs_contents,
+    task_definitions,
     get_by_path
 };
 use crate::modules::docs::format_element;
+use crate::modules::lint::lint_definitions;
 
 /// Style of the CLI.
 const STYLE: Styles = Styles::styled()
@@ -51,13 +61,43 @@ fn cli_cmds() -> Command {
         .subcommand(
             Command::new("run")
                 .about("Prompts for a task file and executes the specified tasks")
-                .arg(arg!(-r --report <FILE> "Custom report file")
+                .arg(arg!(-r --report <FILE> "Write a JSON report of the whole run to this file")
                     .required(false)
                     .value_parser(value_parser!(PathBuf))
                 )
                 .arg(arg!( -f --file <FILE> "Custom task YAML file")
                     .required(false)
                     .value_parser(value_parser!(PathBuf)))
+                .arg(arg!(--timeout <DURATION> "Time limit for the whole run, e.g. 90s, 15m or 2h, 0 for none")
+                    .required(false)
+                    .value_parser(duration_arg))
+                .arg(arg!(--"fail-fast" "Skip the remaining tasks once a task fails")
+                    .conflicts_with_all(["keep-going", "rollback"]))
+                .arg(arg!(--"keep-going" "Run every task even when earlier tasks fail (default)")
+                    .conflicts_with("rollback"))
+                .arg(arg!(--rollback "Skip the remaining tasks once a task fails, then undo the tasks that succeeded with their +Undo scripts"))
+                .args(variable_args())
+                .arg(arg!(-j --jobs <N> "Most tasks to run at the same time, defaults to 4")
+                    .required(false)
+                    .value_parser(value_parser!(u16).range(1..)))
+                .arg(arg!(--resume <RUN_ID> "Pick up an earlier run of the same task file, running only the tasks that did not succeed")
+                    .required(false)
+                    .value_parser(value_parser!(String)))
+        )
+        .subcommand(
+            Command::new("plan")
+                .about("Shows what a task file would run, with the passthru data and script of every task, without running anything")
+                .arg(arg!( -f --file <FILE> "Custom task YAML file")
+                    .required(false)
+                    .value_parser(value_parser!(PathBuf)))
+                .args(variable_args())
+                .arg(arg!(-o --output <DIR> "Write the script of every task to this directory instead of printing it")
+                    .required(false)
+                    .value_parser(value_parser!(PathBuf)))
+        )
+        .subcommand(
+            Command::new("lint")
+                .about("Checks the task definitions and the scripts of the tasks folder for mistakes")
         )
         .subcommand(
             Command::new("generate-docs")
@@ -78,31 +118,36 @@ fn cli_cmds() -> Command {
 }
 
 /// Top-level logic for handling CLI arguments and their functions.
-pub async fn handle_cli() {
+pub async fn handle_cli() -> ExitCode {
     let cmd = cli_cmds();
     let matches = cmd.get_matches();
 
     match matches.try_get_one::<bool>("verbose") {
-        Ok(a) => if let Some(b) = a { 
-            if *b { log::set_max_level(log::LevelFilter::Trace) }
+        Ok(a) => if let Some(b) = a && *b { 
+            log::set_max_level(log::LevelFilter::Trace)
         },
         Err(e) => {
             error!("Error occured when handling \"verbose\" argument, skipping: {}", anyhow!(e))
         }
     }
 
-    match matches.subcommand_name() {
-        Some("run") => {
-            if let Err(err) = run_cmd(&matches).await { error!("Error occured when using \"run\" command: {}", anyhow!(err)) };
-        },
-        Some("generate-docs") => {
-            if let Err(err) = generate_docs_cmd(&matches).await { error!("Error occured when using \"generate-docs\" command: {}", anyhow!(err)) };
-        }
+    let (command, result) = match matches.subcommand_name() {
+        Some("run") => ("\"run\" command", run_cmd(&matches).await),
+        Some("plan") => ("\"plan\" command", plan_cmd(&matches).await),
+        Some("lint") => ("\"lint\" command", lint_cmd().await),
+        Some("generate-docs") => ("\"generate-docs\" command", generate_docs_cmd(&matches).await),
         Some(&_) => {
             unimplemented!("Unknown command provided.")
         }
-        None => {
-            if let Err(err) = run_cmd(&matches).await { error!("Error occured when using default command: {}", anyhow!(err)) };
+        None => ("default command", run_cmd(&matches).await)
+    };
+
+    // A command that fails exits with a non-zero code, so scripts and CI jobs can tell.
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(err) => {
+            error!("Error occured when using {}: {}", command, anyhow!(err));
+            ExitCode::FAILURE
         }
     }
 }
@@ -118,7 +163,7 @@ async fn handle_file_dialog() -> Result<PathBuf> {
     
         match file {
             Some(d) => {
-                return Ok(d.path().to_path_buf())
+                Ok(d.path().to_path_buf())
             },
             None => {
                 Err(anyhow!("No task YAML specified. Closing Taskmaster."))
@@ -140,7 +185,7 @@ async fn handle_directory_dialog() -> Result<PathBuf> {
     
         match dir {
             Some(d) => {
-                return Ok(d.path().to_path_buf())
+                Ok(d.path().to_path_buf())
             },
             None => {
                 Err(anyhow!("No output folder specified. Closing Taskmaster."))
@@ -154,16 +199,13 @@ async fn handle_directory_dialog() -> Result<PathBuf> {
 
 /// Discrete logic for the "run" command.
 pub async fn run_cmd(matches: &ArgMatches) -> Result<()> {
-    let user_input_path = match matches.try_get_one::<PathBuf>("file_input").to_owned() {
-        Ok(b) => match b {
-            Some(c) => c.to_owned(),
-            None => {
-                handle_file_dialog().await?
-            }
-        },
-        Err(_) => {
-            let b = match matches.subcommand_matches("run") {
-                Some(c) => match c.clone().try_get_one::<PathBuf>("file") {
+    let run_matches = matches.subcommand_matches("run");
+
+    let user_input_path = match matches.try_get_one::<PathBuf>("file_input") {
+        Ok(Some(a)) => a.to_owned(),
+        _ => {
+            match run_matches {
+                Some(c) => match c.try_get_one::<PathBuf>("file") {
                     Ok(d) => match d {
                         Some(e) => e.to_owned(),
                         None => {
@@ -178,23 +220,183 @@ pub async fn run_cmd(matches: &ArgMatches) -> Result<()> {
                 None => { 
                     handle_file_dialog().await?
                 }
-            };
-            b
-
+            }
         }
     };
 
+    let options = run_options(run_matches);
+
     let user_input = file_contents(&user_input_path).await?;
-    let task_input = task_defs_contents().await?;
+    let user_yaml: Value = serde_yml::from_str(&user_input)?;
+    let task_yaml = task_definitions().await?;
+
+    execute_tasks(&user_yaml, &task_yaml, &options).await?;
+
+    Ok(())
+}
+
+/// Builds the options of a run from the arguments of the "run" subcommand.
+fn run_options(sub_matches: Option<&ArgMatches>) -> RunOptions {
+    let mut options = RunOptions::default();
+    let sub_matches = match sub_matches {
+        Some(a) => a,
+        None => return options
+    };
+
+    options.timeout = sub_matches.get_one::<Duration>("timeout").copied().filter(|a| !a.is_zero());
+    options.report = sub_matches.get_one::<PathBuf>("report").cloned();
+    options.resume = sub_matches.get_one::<String>("resume").cloned();
+
+    read_variable_args(sub_matches, &mut options);
+
+    if let Some(a) = sub_matches.get_one::<u16>("jobs") {
+        options.jobs = *a as usize;
+    }
+
+    if sub_matches.get_flag("fail-fast") {
+        options.failure_policy = FailurePolicy::FailFast;
+    }
+
+    if sub_matches.get_flag("rollback") {
+        options.failure_policy = FailurePolicy::Rollback;
+    }
+
+    options
+}
+
+/// Arguments that set the variables of a task file, shared by the "run" and "plan" subcommands.
+fn variable_args() -> [Arg; 2] {
+    [
+        arg!(--var <VAR> "Set a variable for the task file as NAME=value, can be given more than once")
+            .required(false)
+            .action(ArgAction::Append)
+            .value_parser(var_arg),
+        arg!(--"env-file" <FILE> "Read variables from a dotenv file, can be given more than once")
+            .required(false)
+            .action(ArgAction::Append)
+            .value_parser(value_parser!(PathBuf))
+    ]
+}
+
+/// Reads the arguments of *variable_args()* into the options of a run.
+fn read_variable_args(sub_matches: &ArgMatches, options: &mut RunOptions) {
+    if let Some(a) = sub_matches.get_many::<(String, String)>("var") {
+        options.vars = a.cloned().collect();
+    }
+
+    if let Some(a) = sub_matches.get_many::<PathBuf>("env-file") {
+        options.env_files = a.cloned().collect();
+    }
+}
+
+/// Parses a variable argument of the form NAME=value.
+fn var_arg(input: &str) -> Result<(String, String)> {
+    match input.split_once('=') {
+        Some((a, b)) if !a.trim().is_empty() => Ok((a.trim().to_owned(), b.to_owned())),
+        _ => Err(anyhow!("\"{}\" should look like NAME=value.", input))
+    }
+}
+
+/// Parses a duration argument, see *parse_duration()*.
+fn duration_arg(input: &str) -> Result<Duration> {
+    parse_duration(input).ok_or(anyhow!("\"{}\" is not a valid duration (e.g. 90, 90s, 15m, 2h)", input))
+}
+
+/// Discrete logic for the "plan" command.
+pub async fn plan_cmd(matches: &ArgMatches) -> Result<()> {
+    let plan_matches = matches.subcommand_matches("plan").ok_or(anyhow!("The \"plan\" command was not given."))?;
 
+    let user_input_path = match plan_matches.get_one::<PathBuf>("file") {
+        Some(a) => a.to_owned(),
+        None => handle_file_dialog().await?
+    };
+
+    let mut options = RunOptions::default();
+    read_variable_args(plan_matches, &mut options);
+    let output_dir = plan_matches.get_one::<PathBuf>("output");
+
+    let user_input = file_contents(&user_input_path).await?;
     let user_yaml: Value = serde_yml::from_str(&user_input)?;
-    let task_yaml: Value = serde_yml::from_str(&task_input)?;
+    let task_yaml = task_definitions().await?;
+
+    let (run_id, planned) = plan_tasks(&user_yaml, &task_yaml, &options).await?;
+
+    if let Some(a) = output_dir { ensure_dir(a.to_owned()).await? }
 
-    execute_tasks(&user_yaml, &task_yaml).await?;
+    println!("Plan of \"{}\", nothing has been run. Task IDs are those of run \"{}\", a real run gives its tasks other IDs.", user_input_path.display(), run_id);
+
+    let mut failed = 0;
+    for task in &planned {
+        println!();
+        match task {
+            Ok(a) => print_planned_task(a, output_dir).await?,
+            Err(e) => {
+                failed += 1;
+                println!("{}", e);
+            }
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        a => Err(anyhow!("{} of {} task(s) could not be planned.", a, planned.len()))
+    }
+}
+
+/// Prints every problem found in the task definitions.
+pub async fn lint_cmd() -> Result<()> {
+    let task_yaml = task_definitions().await?;
+
+    let problems = lint_definitions(&task_yaml)?;
+    for problem in &problems {
+        println!("{}", problem);
+    }
+
+    match problems.len() {
+        0 => {
+            info!("No problems were found in the {} task definition(s).", task_yaml.as_mapping().map(|a| a.len()).unwrap_or_default());
+            Ok(())
+        },
+        a => Err(anyhow!("{} problem(s) were found in the task definitions.", a))
+    }
+}
+
+/// Prints a planned task, writing its script to *output_dir* instead of printing it if given.
+async fn print_planned_task(task: &PlannedTask, output_dir: Option<&PathBuf>) -> Result<()> {
+    let resolved = match sanitize_string(&task.label) == sanitize_string(&task.name) {
+        true => task.name.to_owned(),
+        false => format!("{} -> {}", task.label, task.name)
+    };
+    let id = task.id.as_ref().map(|a| format!(" [{}]", a)).unwrap_or_default();
+
+    println!("{}. {}{}", task.position + 1, resolved, id);
+    println!("   Shell: {}{}", task.shell, if task.elevated { ", elevated" } else { "" });
+    println!("   Task ID: {}", task.task_id);
+    if !task.waits_for.is_empty() { println!("   Waits for: {}", task.waits_for.join(", ")) }
+    if !task.items.is_empty() { println!("   Item: {}", task.items.join(", ")) }
+    if let Some(a) = &task.when { println!("   When: {}", a) }
+    if !task.defaults.is_empty() { println!("   Defaults: {}", task.defaults.join(", ")) }
+
+    let parameters = serde_json::to_string_pretty(&task.parameters)?;
+    println!("   Passthru:\n{}", indent(&parameters));
+
+    match output_dir {
+        Some(a) => {
+            let path = a.join(format!("{:02}-{}.{}", task.position + 1, task.name, task.extension));
+            create_file(&task.script, path.clone()).await?;
+            println!("   Script: written to \"{}\"", path.display());
+        },
+        None => println!("   Script:\n{}", indent(&task.script))
+    }
 
     Ok(())
 }
 
+/// Indents every line of a block of text for *print_planned_task()*.
+fn indent(text: &str) -> String {
+    text.lines().map(|a| format!("      {}", a)).collect::<Vec<String>>().join("\n")
+}
+
 /// Handles when no arguments are sipplied to the "generate-docs" command.
 async fn handle_no_docs_input() -> Result<(PathBuf, Vec<String>)> {
     info!("No tasks specified. Defaulting to generating documentation for all tasks.");
@@ -261,9 +463,9 @@ pub async fn generate_docs_cmd(matches: &ArgMatches) -> Result<()> {
         }
     };
 
-    let task_file = handle_logged_result(
-        task_defs_contents().await,
-        "Error occured when reading task definition file: "
+    let task_defs_val = handle_logged_result(
+        task_definitions().await,
+        "Error occured when loading task definitions: "
     )?;
 
     let task_defs = handle_logged_result(
@@ -271,11 +473,6 @@ pub async fn generate_docs_cmd(matches: &ArgMatches) -> Result<()> {
         "Error occured when processing task definitions: "
     )?;
 
-    let task_defs_val: Value = handle_logged_result(
-        serde_yml::from_str(&task_file).map_err(|e| anyhow!(e.to_string())),
-        "Error occured when parsing task definitions"
-    )?;
-
     for task in tasks {
         if !task_defs.contains(&task) { continue }
         let contents_val = match get_by_path(&task_defs_val, &task, None) {
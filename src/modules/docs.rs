@@ -41,20 +41,58 @@ fn format_value(data: &Value, identifier: &str) -> String {
         Value::Null => "null".to_string()
     };
 
-    return match identifier.to_lowercase().as_ref() {
+    match identifier.to_lowercase().as_ref() {
         "+description" => format!("{}\n", display_str),
         "+aliases" => format!("- {}\n", display_str), // Aliases will be bullets
         "+data" => String::new(), // This is for other TM operations
         "+example" => format!("```yaml\n{}\n```\n", display_str), // Code blocks, hopefully Obsidian has syntax highlighting
         "+passthru" | "+passthrough" => String::new(), // This is for other TM operations
+        "+timeout" => format!("Timeout: {}\n", display_str),
+        "+reportrequired" => format!("Report required: {}\n", display_str),
+        "+undo" => format!("Undo script: {}\n", display_str),
+        "+type" => format!("Type: {}\n", display_str),
+        "+required" => format!("Required: {}\n", display_str),
+        "+enum" => format!("- {}\n", display_str), // Allowed values will be bullets
+        "+pattern" => format!("Pattern: `{}`\n", display_str),
+        "+min" => format!("Minimum: {}\n", display_str),
+        "+max" => format!("Maximum: {}\n", display_str),
+        "+minitems" => format!("Minimum items: {}\n", display_str),
+        "+maxitems" => format!("Maximum items: {}\n", display_str),
         &_ => format!("{}\n", display_str), // Blindly pass through all data as it must be preserved
     }
 }
 
+/// Formats the "+Retry" of a task, either a number of attempts or a mapping of "Attempts", "Delay" and "Backoff".
+fn format_retry(data: &Value, identifier: &str, depth: i8) -> String {
+    let mut md = add_header(identifier, depth);
+    let mapping = match data.as_mapping() {
+        Some(m) => m,
+        None if data.is_sequence() => return md,
+        None => return md + &format!("Attempts: {}\n", format_value(data, "").trim_end())
+    };
+
+    for (key, value) in mapping {
+        let Some(key_str) = key.as_str() else { continue };
+        if value.is_mapping() || value.is_sequence() {
+            continue;
+        }
+
+        let display_str = format_value(value, "");
+        md += &match key_str.to_lowercase().as_ref() {
+            "attempts" => format!("Attempts: {}", display_str),
+            "delay" => format!("Delay: {}", display_str),
+            "backoff" => format!("Backoff: {}", display_str),
+            &_ => display_str,
+        };
+    }
+
+    md
+}
+
 /// Trims carriage return and newline from a given string slice.
 fn trim_md_str(input: &str) -> String {
     let trim_pattern = "\r\n";
-    return input.trim_end_matches(&trim_pattern).to_owned();
+    input.trim_end_matches(&trim_pattern).to_owned()
 }
 
 /// Converts a serde data structure into Markdown.
@@ -64,6 +102,11 @@ fn trim_md_str(input: &str) -> String {
 /// Depth is handled by the depth of the recursion on the data structure.
 pub fn format_element(data: &Value, identifier: &str, depth: i8) -> String {
     let mut md = String::new();
+
+    // Keys inside "+Retry" are only formatted there, so a key like "Delay" elsewhere is left alone
+    if identifier.eq_ignore_ascii_case("+retry") {
+        return trim_md_str(&format_retry(data, identifier, depth));
+    }
     
     if data.is_mapping() {
         md += &add_header(identifier, depth);
@@ -105,4 +148,22 @@ pub fn format_element(data: &Value, identifier: &str, depth: i8) -> String {
 
     md += &format_value(data, identifier);
     trim_md_str(&md)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_keys_are_only_formatted_inside_retry() {
+        let data: Value = serde_yml::from_str("Download:\n  +Retry:\n    Attempts: 3\n    Delay: 10s\n  Delay:\n    +Type: string\n").unwrap();
+        let md = format_element(&data, "+", 0);
+        assert!(md.contains("Attempts: 3\nDelay: 10s\n"), "{}", md);
+        assert!(md.contains("## Delay\nType: string"), "{}", md);
+    }
+
+    #[test]
+    fn retry_as_a_number_is_its_attempts() {
+        let data: Value = serde_yml::from_str("+Retry: 3").unwrap();
+        assert!(format_element(&data, "+", 0).contains("Retry\nAttempts: 3"));
+    }
+}
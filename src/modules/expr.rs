@@ -0,0 +1,564 @@
+use anyhow::{Result, anyhow};
+
+use std::{
+    env,
+    fmt,
+    path::Path
+};
+
+/// A value an expression evaluates to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Text(String)
+}
+
+impl ExprValue {
+    /// Whether the value counts as true for a condition.
+    ///
+    /// Null, false, 0 and empty text are false, as is text reading "false", "no" or "0", since that is how
+    /// environment variables usually turn things off.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            ExprValue::Null => false,
+            ExprValue::Bool(a) => *a,
+            ExprValue::Number(a) => *a != 0.0,
+            ExprValue::Text(a) => !matches!(a.trim().to_ascii_lowercase().as_str(), "" | "0" | "false" | "no")
+        }
+    }
+
+    /// Converts a plain YAML value. Lists and maps can't be used in expressions.
+    pub fn from_yaml(value: &serde_yml::Value) -> Result<ExprValue> {
+        match value {
+            serde_yml::Value::Null => Ok(ExprValue::Null),
+            serde_yml::Value::Bool(a) => Ok(ExprValue::Bool(*a)),
+            serde_yml::Value::Number(a) => a.as_f64().map(ExprValue::Number).ok_or(anyhow!("{} is not a usable number.", a)),
+            serde_yml::Value::String(a) => Ok(ExprValue::Text(a.to_owned())),
+            a => Err(anyhow!("Lists and maps can't be used in a condition, found {:?}.", a))
+        }
+    }
+
+    /// The value as a number, if it is one or is text that reads as one.
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            ExprValue::Number(a) => Some(*a),
+            ExprValue::Text(a) => a.trim().parse::<f64>().ok(),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for ExprValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExprValue::Null => write!(f, "null"),
+            ExprValue::Bool(a) => write!(f, "{}", a),
+            ExprValue::Number(a) => write!(f, "{}", a),
+            ExprValue::Text(a) => write!(f, "\"{}\"", a)
+        }
+    }
+}
+
+/// How two values are compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual
+}
+
+/// A parsed expression, e.g. `env.DEPARTMENT == "Sales" and not exists("C:/Program Files/App")`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(ExprValue),
+    /// A dotted name such as `env.USERNAME`, `host.os` or `tasks.install.status`.
+    Name(Vec<String>),
+    /// A function call such as `exists("C:/Temp")`.
+    Call(String, Vec<Expr>),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(CompareOp, Box<Expr>, Box<Expr>)
+}
+
+/// The condition of a task, as written and as parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub source: String,
+    pub expr: Expr
+}
+
+impl Condition {
+    /// Parses the condition of a task from its "When", which is either an expression or a plain true/false.
+    pub fn from_value(value: &serde_yml::Value) -> Result<Condition> {
+        match value {
+            serde_yml::Value::Bool(a) => Ok(Condition { source: a.to_string(), expr: Expr::Literal(ExprValue::Bool(*a)) }),
+            serde_yml::Value::String(a) => Ok(Condition { source: a.to_owned(), expr: parse_expr(a)? }),
+            a => Err(anyhow!("A condition should be an expression such as 'env.DEPARTMENT == \"Sales\"', not {:?}.", a))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Text(String),
+    Number(f64),
+    Compare(CompareOp),
+    And,
+    Or,
+    Not,
+    Dot,
+    Comma,
+    Open,
+    Close
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Name(a) => write!(f, "\"{}\"", a),
+            Token::Text(a) => write!(f, "the text \"{}\"", a),
+            Token::Number(a) => write!(f, "the number {}", a),
+            Token::Compare(_) => write!(f, "a comparison"),
+            Token::And => write!(f, "\"and\""),
+            Token::Or => write!(f, "\"or\""),
+            Token::Not => write!(f, "\"not\""),
+            Token::Dot => write!(f, "\".\""),
+            Token::Comma => write!(f, "\",\""),
+            Token::Open => write!(f, "\"(\""),
+            Token::Close => write!(f, "\")\"")
+        }
+    }
+}
+
+/// Splits an expression into tokens.
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        if c.is_whitespace() {
+            i += 1;
+            continue
+        }
+
+        let (token, length) = match (c, next) {
+            ('=', Some('=')) => (Token::Compare(CompareOp::Equal), 2),
+            ('!', Some('=')) => (Token::Compare(CompareOp::NotEqual), 2),
+            ('<', Some('=')) => (Token::Compare(CompareOp::LessOrEqual), 2),
+            ('>', Some('=')) => (Token::Compare(CompareOp::GreaterOrEqual), 2),
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('<', _) => (Token::Compare(CompareOp::Less), 1),
+            ('>', _) => (Token::Compare(CompareOp::Greater), 1),
+            ('!', _) => (Token::Not, 1),
+            ('.', _) => (Token::Dot, 1),
+            (',', _) => (Token::Comma, 1),
+            ('(', _) => (Token::Open, 1),
+            (')', _) => (Token::Close, 1),
+            ('=', _) => return Err(anyhow!("Use \"==\" to check if two values are the same, not \"=\".")),
+            ('"' | '\'', _) => {
+                let end = chars[i + 1..].iter().position(|a| *a == c)
+                    .ok_or(anyhow!("The text starting at {} is missing its closing {}.", i + 1, c))?;
+                let text: String = chars[i + 1..i + 1 + end].iter().collect();
+                (Token::Text(text), end + 2)
+            },
+            (a, _) if a.is_ascii_digit() || (a == '-' && next.is_some_and(|b| b.is_ascii_digit())) => {
+                let length = 1 + chars[i + 1..].iter().take_while(|b| b.is_ascii_digit() || **b == '.').count();
+                let text: String = chars[i..i + length].iter().collect();
+                let number = text.parse::<f64>().map_err(|_| anyhow!("\"{}\" is not a valid number.", text))?;
+                (Token::Number(number), length)
+            },
+            (a, _) if a.is_alphabetic() || a == '_' => {
+                let length = chars[i..].iter().take_while(|b| b.is_alphanumeric() || **b == '_' || **b == '-').count();
+                let word: String = chars[i..i + length].iter().collect();
+                let token = match word.to_ascii_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Name(word)
+                };
+                (token, length)
+            },
+            (a, _) => return Err(anyhow!("Unexpected \"{}\" at position {}.", a, i + 1))
+        };
+
+        tokens.push(token);
+        i += length;
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser over the tokens of an expression, from the loosest binding operator to the tightest:
+/// or, and, not, comparisons, then single values.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token, context: &str) -> Result<()> {
+        match self.next() {
+            Some(a) if a == expected => Ok(()),
+            Some(a) => Err(anyhow!("Expected {} {}, but found {}.", expected, context, a)),
+            None => Err(anyhow!("Expected {} {}, but the expression ended.", expected, context))
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut left = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut left = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            left = Expr::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.not()?)))
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<Expr> {
+        let left = self.value()?;
+        match self.peek() {
+            Some(Token::Compare(op)) => {
+                let op = *op;
+                self.next();
+                Ok(Expr::Compare(op, Box::new(left), Box::new(self.value()?)))
+            },
+            _ => Ok(left)
+        }
+    }
+
+    fn value(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Text(a)) => Ok(Expr::Literal(ExprValue::Text(a))),
+            Some(Token::Number(a)) => Ok(Expr::Literal(ExprValue::Number(a))),
+            Some(Token::Open) => {
+                let inner = self.or()?;
+                self.expect(Token::Close, "to close the bracket")?;
+                Ok(inner)
+            },
+            Some(Token::Name(a)) => {
+                match a.to_ascii_lowercase().as_str() {
+                    "true" => return Ok(Expr::Literal(ExprValue::Bool(true))),
+                    "false" => return Ok(Expr::Literal(ExprValue::Bool(false))),
+                    "null" => return Ok(Expr::Literal(ExprValue::Null)),
+                    _ => {}
+                }
+
+                if self.peek() == Some(&Token::Open) {
+                    self.next();
+                    let mut args = Vec::new();
+                    if self.peek() != Some(&Token::Close) {
+                        args.push(self.or()?);
+                        while self.peek() == Some(&Token::Comma) {
+                            self.next();
+                            args.push(self.or()?);
+                        }
+                    }
+                    self.expect(Token::Close, &format!("to close the call to \"{}\"", a))?;
+                    return Ok(Expr::Call(a, args))
+                }
+
+                let mut parts = vec![a];
+                while self.peek() == Some(&Token::Dot) {
+                    self.next();
+                    match self.next() {
+                        Some(Token::Name(b)) => parts.push(b),
+                        Some(Token::Number(b)) => parts.push(b.to_string()),
+                        _ => return Err(anyhow!("Expected a name after \"{}.\".", parts.join(".")))
+                    }
+                }
+                Ok(Expr::Name(parts))
+            },
+            Some(a) => Err(anyhow!("Expected a value, but found {}.", a)),
+            None => Err(anyhow!("Expected a value, but the expression ended."))
+        }
+    }
+}
+
+/// Parses an expression such as `host.os == "windows" and (env.DEPARTMENT == "Sales" or exists("D:/Data"))`.
+///
+/// Supports ==, !=, <, <=, >, >=, and/&&, or/||, not/!, brackets, 'text', "text", numbers, true, false, null,
+/// dotted names and function calls.
+pub fn parse_expr(input: &str) -> Result<Expr> {
+    let tokens = tokenize(input).map_err(|e| anyhow!("Could not read the expression '{}': {}", input, e))?;
+    let mut parser = Parser { tokens, position: 0 };
+
+    let expr = parser.or().map_err(|e| anyhow!("Could not read the expression '{}': {}", input, e))?;
+    if let Some(a) = parser.peek() {
+        return Err(anyhow!("Could not read the expression '{}': unexpected {} after the end of the expression.", input, a))
+    }
+
+    Ok(expr)
+}
+
+impl Expr {
+    /// Ids of the tasks the expression looks at through `tasks.<id>`.
+    pub fn task_refs(&self) -> Vec<String> {
+        match self {
+            Expr::Literal(_) => Vec::new(),
+            Expr::Name(a) if a.len() > 1 && a[0].eq_ignore_ascii_case("tasks") => vec![a[1].to_owned()],
+            Expr::Name(_) => Vec::new(),
+            Expr::Call(_, a) => a.iter().flat_map(|b| b.task_refs()).collect(),
+            Expr::Not(a) => a.task_refs(),
+            Expr::And(a, b) | Expr::Or(a, b) | Expr::Compare(_, a, b) => {
+                let mut refs = a.task_refs();
+                refs.extend(b.task_refs());
+                refs
+            }
+        }
+    }
+
+    /// Evaluates the expression.
+    ///
+    /// `env.<NAME>` and `host.<fact>` are looked up here. Any other dotted name, such as `tasks.<id>.status`,
+    /// is passed to *resolve* whole.
+    pub fn evaluate(&self, resolve: &dyn Fn(&[String]) -> Result<ExprValue>) -> Result<ExprValue> {
+        match self {
+            Expr::Literal(a) => Ok(a.to_owned()),
+            Expr::Name(a) => match a[0].to_ascii_lowercase().as_str() {
+                "env" => env_value(a),
+                "host" => host_fact(a),
+                _ => resolve(a)
+            },
+            Expr::Call(name, args) => call(name, args, resolve),
+            Expr::Not(a) => Ok(ExprValue::Bool(!a.evaluate(resolve)?.is_truthy())),
+            Expr::And(a, b) => Ok(ExprValue::Bool(a.evaluate(resolve)?.is_truthy() && b.evaluate(resolve)?.is_truthy())),
+            Expr::Or(a, b) => Ok(ExprValue::Bool(a.evaluate(resolve)?.is_truthy() || b.evaluate(resolve)?.is_truthy())),
+            Expr::Compare(op, a, b) => compare(*op, &a.evaluate(resolve)?, &b.evaluate(resolve)?).map(ExprValue::Bool)
+        }
+    }
+}
+
+/// Looks up `env.<NAME>`, which is null when the variable is not set.
+fn env_value(name: &[String]) -> Result<ExprValue> {
+    match name {
+        [_, var] => Ok(env::var(var).map(ExprValue::Text).unwrap_or(ExprValue::Null)),
+        _ => Err(anyhow!("Use \"env.NAME\" to check an environment variable, not \"{}\".", name.join(".")))
+    }
+}
+
+/// Looks up `host.<fact>`: os ("windows", "linux", "macos"), family ("windows", "unix"), arch, name or user.
+fn host_fact(name: &[String]) -> Result<ExprValue> {
+    let fact = match name {
+        [_, a] => a.to_ascii_lowercase(),
+        _ => return Err(anyhow!("Use \"host.os\", \"host.family\", \"host.arch\", \"host.name\" or \"host.user\", not \"{}\".", name.join(".")))
+    };
+
+    match fact.as_str() {
+        "os" => Ok(ExprValue::Text(env::consts::OS.to_owned())),
+        "family" => Ok(ExprValue::Text(env::consts::FAMILY.to_owned())),
+        "arch" => Ok(ExprValue::Text(env::consts::ARCH.to_owned())),
+        "name" => Ok(whoami::fallible::hostname().map(ExprValue::Text).unwrap_or(ExprValue::Null)),
+        "user" => Ok(ExprValue::Text(whoami::username())),
+        _ => Err(anyhow!("Unknown host fact \"{}\", expected os, family, arch, name or user.", fact))
+    }
+}
+
+/// Calls a function of an expression. Only `exists(path)` is supported.
+fn call(name: &str, args: &[Expr], resolve: &dyn Fn(&[String]) -> Result<ExprValue>) -> Result<ExprValue> {
+    match (name.to_ascii_lowercase().as_str(), args) {
+        ("exists", [a]) => match a.evaluate(resolve)? {
+            ExprValue::Text(b) => Ok(ExprValue::Bool(Path::new(&b).exists())),
+            b => Err(anyhow!("exists() needs the path of a file or folder, not {}.", b))
+        },
+        ("exists", _) => Err(anyhow!("exists() takes exactly one path, e.g. exists(\"C:/Temp\").")),
+        _ => Err(anyhow!("Unknown function \"{}\", only exists(path) is supported.", name))
+    }
+}
+
+/// Compares two values. Text is compared ignoring case, and numbers numerically even when one side is text.
+fn compare(op: CompareOp, left: &ExprValue, right: &ExprValue) -> Result<bool> {
+    if matches!(op, CompareOp::Equal | CompareOp::NotEqual) {
+        let equal = match (left, right) {
+            (ExprValue::Text(a), ExprValue::Text(b)) => a.eq_ignore_ascii_case(b),
+            (ExprValue::Bool(a), ExprValue::Text(b)) | (ExprValue::Text(b), ExprValue::Bool(a)) => b.eq_ignore_ascii_case(&a.to_string()),
+            (a, b) => match (a.as_number(), b.as_number()) {
+                (Some(c), Some(d)) => c == d,
+                _ => a == b
+            }
+        };
+
+        return Ok(equal == (op == CompareOp::Equal))
+    }
+
+    let ordering = match (left.as_number(), right.as_number(), left, right) {
+        (Some(a), Some(b), _, _) => a.partial_cmp(&b),
+        (_, _, ExprValue::Text(a), ExprValue::Text(b)) => Some(a.to_lowercase().cmp(&b.to_lowercase())),
+        _ => None
+    };
+
+    let ordering = ordering.ok_or(anyhow!("Can not tell whether {} is more or less than {}.", left, right))?;
+
+    Ok(match op {
+        CompareOp::Less => ordering.is_lt(),
+        CompareOp::LessOrEqual => ordering.is_le(),
+        CompareOp::Greater => ordering.is_gt(),
+        CompareOp::GreaterOrEqual => ordering.is_ge(),
+        CompareOp::Equal | CompareOp::NotEqual => unreachable!()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Evaluates an expression where every name not handled by the evaluator itself is looked up in *names*.
+    fn eval(input: &str, names: &[(&str, ExprValue)]) -> Result<ExprValue> {
+        let resolve = |name: &[String]| {
+            let name = name.join(".");
+            names.iter().find(|(a, _)| *a == name).map(|(_, b)| b.to_owned()).ok_or(anyhow!("unknown name \"{}\"", name))
+        };
+        parse_expr(input)?.evaluate(&resolve)
+    }
+
+    fn truthy(input: &str) -> bool {
+        eval(input, &[]).unwrap().is_truthy()
+    }
+
+    fn text(a: &str) -> Expr {
+        Expr::Literal(ExprValue::Text(a.to_owned()))
+    }
+
+    #[test]
+    fn tokenize_splits_operators_and_values() {
+        assert_eq!(tokenize("a.b >= -1.5 && !x").unwrap(), vec![
+            Token::Name("a".to_owned()),
+            Token::Dot,
+            Token::Name("b".to_owned()),
+            Token::Compare(CompareOp::GreaterOrEqual),
+            Token::Number(-1.5),
+            Token::And,
+            Token::Not,
+            Token::Name("x".to_owned())
+        ]);
+        assert_eq!(tokenize("AND Or not").unwrap(), vec![Token::And, Token::Or, Token::Not]);
+    }
+
+    #[test]
+    fn tokenize_reads_quoted_text() {
+        assert_eq!(tokenize("'say \"hi\"'").unwrap(), vec![Token::Text("say \"hi\"".to_owned())]);
+        assert_eq!(tokenize("\"it's\"").unwrap(), vec![Token::Text("it's".to_owned())]);
+        assert_eq!(tokenize("'a and b'").unwrap(), vec![Token::Text("a and b".to_owned())]);
+        assert_eq!(tokenize("''").unwrap(), vec![Token::Text(String::new())]);
+    }
+
+    #[test]
+    fn tokenize_rejects_bad_input() {
+        assert!(tokenize("'open").is_err());
+        assert!(tokenize("a = b").is_err());
+        assert!(tokenize("a # b").is_err());
+        assert!(tokenize("1.2.3").is_err());
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let expr = parse_expr("'a' or 'b' and 'c'").unwrap();
+        assert_eq!(expr, Expr::Or(Box::new(text("a")), Box::new(Expr::And(Box::new(text("b")), Box::new(text("c"))))));
+
+        assert!(truthy("true or false and false"));
+        assert!(!truthy("(true or false) and false"));
+    }
+
+    #[test]
+    fn not_binds_looser_than_comparisons() {
+        let expr = parse_expr("not 'a' == 'b'").unwrap();
+        assert_eq!(expr, Expr::Not(Box::new(Expr::Compare(CompareOp::Equal, Box::new(text("a")), Box::new(text("b"))))));
+
+        assert!(truthy("not 1 == 2"));
+        assert!(truthy("not false and true"));
+        assert!(truthy("!!true"));
+    }
+
+    #[test]
+    fn parse_reads_names_and_calls() {
+        assert_eq!(parse_expr("tasks.probe.failed").unwrap(), Expr::Name(vec!["tasks".to_owned(), "probe".to_owned(), "failed".to_owned()]));
+        assert_eq!(parse_expr("exists('C:/App', \"x\")").unwrap(), Expr::Call("exists".to_owned(), vec![text("C:/App"), text("x")]));
+        assert_eq!(parse_expr("NULL").unwrap(), Expr::Literal(ExprValue::Null));
+    }
+
+    #[test]
+    fn parse_rejects_bad_input() {
+        assert!(parse_expr("").is_err());
+        assert!(parse_expr("(true").is_err());
+        assert!(parse_expr("true)").is_err());
+        assert!(parse_expr("true and").is_err());
+        assert!(parse_expr("1 == == 2").is_err());
+        assert!(parse_expr("a.").is_err());
+        assert!(parse_expr("exists('a'").is_err());
+    }
+
+    #[test]
+    fn compare_text_ignores_case_and_numbers_compare_as_numbers() {
+        assert!(truthy("'Windows' == 'windows'"));
+        assert!(truthy("'10' == 10.0"));
+        assert!(truthy("'10' > 9"));
+        assert!(truthy("'b' > 'A'"));
+        assert!(truthy("true == 'TRUE'"));
+        assert!(truthy("null != 'null'"));
+        assert!(eval("true < 1", &[]).is_err());
+    }
+
+    #[test]
+    fn evaluate_resolves_task_names() {
+        let names = [("tasks.probe.failed", ExprValue::Bool(true)), ("tasks.probe.status", ExprValue::Text("failed".to_owned()))];
+        assert_eq!(eval("tasks.probe.failed and tasks.probe.status == 'Failed'", &names).unwrap(), ExprValue::Bool(true));
+        assert!(eval("tasks.other.failed", &names).is_err());
+        assert_eq!(parse_expr("tasks.a.failed or not tasks.b.succeeded").unwrap().task_refs(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn evaluate_rejects_unknown_functions_and_facts() {
+        assert!(eval("missing('a')", &[]).is_err());
+        assert!(eval("exists()", &[]).is_err());
+        assert!(eval("exists(1)", &[]).is_err());
+        assert!(eval("host.color", &[]).is_err());
+        assert!(eval("env.A.B", &[]).is_err());
+    }
+
+    #[test]
+    fn text_reading_off_is_not_truthy() {
+        for a in ["", "0", "false", "No", " FALSE "] {
+            assert!(!ExprValue::Text(a.to_owned()).is_truthy(), "{:?}", a);
+        }
+        assert!(ExprValue::Text("yes".to_owned()).is_truthy());
+        assert!(!ExprValue::Number(0.0).is_truthy());
+        assert!(!ExprValue::Null.is_truthy());
+    }
+}
@@ -0,0 +1,468 @@
+use anyhow::{Result, anyhow};
+
+use serde_yml::{Sequence, Value};
+
+use std::collections::HashMap;
+
+use crate::modules::expr::Condition;
+use crate::modules::task::tasks_from_map;
+use crate::modules::utils::sanitize_string;
+use crate::modules::vars::{Variables, interpolate, task_output_refs};
+
+/// A task of the run, along with the tasks it has to wait for.
+#[derive(Debug, Clone)]
+pub struct TaskNode {
+    /// Position of the task in the run, counting the tasks inside "Parallel" blocks.
+    pub position: usize,
+    /// The user's task, a mapping of the task's name to its data.
+    pub user_task: Value,
+    /// "Id" the user gave the task, which other tasks can name in their "Needs".
+    pub id: Option<String>,
+    /// Positions of the tasks named in "Needs" or whose outputs it uses, which have to succeed before this one can start.
+    pub needs: Vec<usize>,
+    /// Positions of the tasks that only have to finish before this one starts, however they ended.
+    pub after: Vec<usize>,
+    /// "When" of the task, which has to hold for it to run.
+    pub when: Option<Condition>,
+    /// Item variables of this invocation, if the task is expanded by "ForEach" or "Matrix".
+    pub bindings: Bindings
+}
+
+/// Item variables of one invocation of a task expanded by "ForEach" or "Matrix", such as "item" or "matrix.os".
+pub type Bindings = Vec<(String, Value)>;
+
+impl TaskNode {
+    /// Name the task is shown under before it is resolved, its Id if it has one.
+    pub fn label(&self) -> String {
+        match &self.id {
+            Some(a) => a.to_owned(),
+            None => task_label(&self.user_task, self.position)
+        }
+    }
+
+    /// Looks up an item variable of this invocation. Names are matched ignoring case, and "matrix.<name>" matches the
+    /// names of the "Matrix" the same way as other keys of a task.
+    pub fn binding(&self, name: &str) -> Option<Value> {
+        let wanted = match name.split_once('.') {
+            Some((a, b)) if a.eq_ignore_ascii_case("matrix") => format!("matrix.{}", sanitize_string(b)),
+            _ => name.to_lowercase()
+        };
+
+        self.bindings.iter().find(|(a, _)| *a == wanted).map(|(_, b)| b.to_owned())
+    }
+}
+
+/// Name a user task is shown under before it is resolved, falling back to its position in the run.
+pub fn task_label(user_task: &Value, position: usize) -> String {
+    match user_task.as_mapping().and_then(|a| a.keys().next()).and_then(|a| a.as_str()) {
+        Some(a) => a.to_owned(),
+        None => format!("task {}", position + 1)
+    }
+}
+
+/// Returns the tasks of a "Parallel" block, if the entry of the task list is one.
+fn parallel_block(entry: &Value) -> Option<Result<Vec<Value>>> {
+    let map = entry.as_mapping()?;
+    if map.len() != 1 { return None }
+
+    let inner = map.get("parallel")?;
+    match inner {
+        Value::Sequence(a) => Some(Ok(a.to_owned())),
+        Value::Mapping(_) => tasks_from_map(inner).map(Ok),
+        _ => Some(Err(anyhow!("A \"Parallel\" block should hold a list of tasks, not {:?}.", inner)))
+    }
+}
+
+/// The keys of a user task that decide where it goes in the graph.
+#[derive(Default)]
+struct GraphKeys {
+    id: Option<String>,
+    needs: Option<Vec<String>>,
+    when: Option<Condition>,
+    /// Ids of the tasks whose outputs the task uses.
+    output_refs: Vec<String>
+}
+
+/// Reads the "Id", "Needs" and "When" of a user task, and the tasks whose outputs it uses.
+fn task_graph_keys(user_task: &Value, position: usize) -> Result<GraphKeys> {
+    let label = task_label(user_task, position);
+    let data = match user_task.as_mapping().and_then(|a| a.values().next()) {
+        Some(a) => a,
+        None => return Ok(GraphKeys::default())
+    };
+
+    let id = match data.get("id") {
+        None | Some(Value::Null) => None,
+        Some(Value::String(a)) => Some(a.to_owned()),
+        Some(a) => return Err(anyhow!("The Id of task \"{}\" should be text, not {:?}.", label, a))
+    };
+
+    let needs = match data.get("needs") {
+        None | Some(Value::Null) => None,
+        Some(Value::String(a)) => Some(vec![a.to_owned()]),
+        Some(Value::Sequence(a)) => {
+            let mut needs = Vec::new();
+            for b in a {
+                match b.as_str() {
+                    Some(c) => needs.push(c.to_owned()),
+                    None => return Err(anyhow!("The Needs of task \"{}\" should only hold task Ids, not {:?}.", label, b))
+                }
+            }
+            Some(needs)
+        },
+        Some(a) => return Err(anyhow!("The Needs of task \"{}\" should be a task Id or a list of them, not {:?}.", label, a))
+    };
+
+    let when = match data.get("when") {
+        None | Some(Value::Null) => None,
+        Some(a) => Some(Condition::from_value(a).map_err(|e| anyhow!("The When of task \"{}\" is not valid. {}", label, e))?)
+    };
+
+    Ok(GraphKeys { id, needs, when, output_refs: task_output_refs(data) })
+}
+
+/// Expands a user task with "ForEach" or "Matrix" into the item variables of each of its invocations, in order.
+///
+/// "ForEach" runs the task once per item of a list, as "${item}" or the name given in "As". "Matrix" maps names to
+/// lists and runs the task once per combination, as "${matrix.<name>}". Either may also be a "${variable}" holding a
+/// list. Returns None for a task with neither, which runs once.
+fn expand_task(user_task: &Value, position: usize, variables: &Variables) -> Result<Option<Vec<Bindings>>> {
+    let label = task_label(user_task, position);
+    let data = match user_task.as_mapping().and_then(|a| a.values().next()) {
+        Some(a) => a,
+        None => return Ok(None)
+    };
+
+    let items = |value: &Value, what: &str| -> Result<Sequence> {
+        match interpolate(value, &|name| variables.get(name), "") {
+            Ok(Value::Sequence(a)) => Ok(a),
+            Ok(a) => Err(anyhow!("The {} of task \"{}\" should be a list, not {:?}.", what, label, a)),
+            Err(e) => Err(anyhow!("The {} of task \"{}\" could not be read, as it uses an {}.", what, label, e))
+        }
+    };
+
+    let for_each = data.get("foreach").filter(|a| !a.is_null());
+    let matrix = data.get("matrix").filter(|a| !a.is_null());
+
+    match (for_each, matrix) {
+        (None, None) => Ok(None),
+        (Some(_), Some(_)) => Err(anyhow!("Task \"{}\" has both ForEach and Matrix, it can only have one of them.", label)),
+        (Some(a), None) => {
+            let name = match data.get("as") {
+                None | Some(Value::Null) => "item".to_owned(),
+                Some(Value::String(b)) => b.trim().to_lowercase(),
+                Some(b) => return Err(anyhow!("The As of task \"{}\" should be a variable name, not {:?}.", label, b))
+            };
+
+            Ok(Some(items(a, "ForEach")?.into_iter().map(|b| vec![(name.clone(), b)]).collect()))
+        },
+        (None, Some(a)) => {
+            let axes = a.as_mapping()
+                .ok_or(anyhow!("The Matrix of task \"{}\" should map names to lists of values, not {:?}.", label, a))?;
+
+            // Every combination, the last name changing fastest.
+            let mut combinations: Vec<Bindings> = vec![Vec::new()];
+            for (axis, values) in axes {
+                let axis = axis.as_str()
+                    .ok_or(anyhow!("The Matrix of task \"{}\" should only have text names, not {:?}.", label, axis))?;
+                let values = items(values, &format!("Matrix entry \"{}\"", axis))?;
+
+                combinations = combinations.iter()
+                    .flat_map(|b| values.iter().map(move |c| {
+                        let mut d = b.to_owned();
+                        d.push((format!("matrix.{}", axis), c.to_owned()));
+                        d
+                    }))
+                    .collect();
+            }
+
+            Ok(Some(combinations))
+        }
+    }
+}
+
+/// Lays out the tasks of a run as a graph, in which every task lists the tasks it has to wait for.
+///
+/// Without "Needs", a task waits for the entry before it in the list to finish: the task before it, or every task of
+/// the "Parallel" block before it. Tasks in the same "Parallel" block don't wait for each other.
+/// With "Needs", a task waits only for the tasks with those Ids, and is skipped if any of them do not succeed.
+/// A task also waits for every task its "When" looks at, and needs every task whose outputs it uses.
+///
+/// A task with "ForEach" or "Matrix" becomes one task per invocation. Outside of a "Parallel" block they run one after
+/// another, inside one they run side by side. Their Ids get the number of the invocation, e.g. "install-1", and needing
+/// the plain Id needs every invocation.
+///
+/// Fails when an Id is used twice, a task needs or uses the outputs of an Id that no task has, or tasks need each
+/// other in a cycle.
+pub fn build_task_graph(entries: &[Value], variables: &Variables) -> Result<Vec<TaskNode>> {
+    let mut nodes: Vec<TaskNode> = Vec::new();
+    let mut explicit_needs: Vec<Option<Vec<String>>> = Vec::new();
+    let mut output_refs: Vec<Vec<String>> = Vec::new();
+    // Ids of expanded tasks, along with the positions of their invocations.
+    let mut groups: HashMap<String, Vec<usize>> = HashMap::new();
+    let mut previous: Vec<usize> = Vec::new();
+
+    let mut add_task = |user_task: &Value, previous: &[usize], in_block: bool| -> Result<Vec<usize>> {
+        let keys = task_graph_keys(user_task, nodes.len())?;
+        let invocations = expand_task(user_task, nodes.len(), variables)?;
+        let expanded = invocations.is_some();
+
+        let mut positions: Vec<usize> = Vec::new();
+        let mut after = previous.to_vec();
+        for (index, bindings) in invocations.unwrap_or(vec![Vec::new()]).into_iter().enumerate() {
+            let position = nodes.len();
+            let id = match expanded {
+                true => keys.id.as_ref().map(|a| format!("{}-{}", a, index + 1)),
+                false => keys.id.clone()
+            };
+
+            nodes.push(TaskNode {
+                position,
+                user_task: user_task.to_owned(),
+                id,
+                needs: Vec::new(),
+                after: after.clone(),
+                when: keys.when.clone(),
+                bindings
+            });
+            explicit_needs.push(keys.needs.clone());
+            output_refs.push(keys.output_refs.clone());
+
+            if !in_block { after = vec![position] }
+            positions.push(position);
+        }
+
+        if expanded && let Some(id) = &keys.id && groups.insert(id.to_owned(), positions.clone()).is_some() {
+            return Err(anyhow!("Two tasks have the Id \"{}\", Ids have to be unique.", id))
+        }
+
+        Ok(positions)
+    };
+
+    for entry in entries {
+        let block = match parallel_block(entry) {
+            Some(a) => a?,
+            None => {
+                let positions = add_task(entry, &previous, false)?;
+                if let Some(a) = positions.last() { previous = vec![*a] }
+                continue
+            }
+        };
+
+        let mut members: Vec<usize> = Vec::new();
+        for user_task in &block {
+            if parallel_block(user_task).is_some() {
+                return Err(anyhow!("\"Parallel\" blocks can not be nested inside each other."))
+            }
+            members.extend(add_task(user_task, &previous, true)?);
+        }
+
+        if !members.is_empty() { previous = members }
+    }
+
+    let mut ids: HashMap<String, usize> = HashMap::new();
+    for node in &nodes {
+        let id = match &node.id { Some(a) => a, None => continue };
+        if let Some(a) = ids.insert(id.to_owned(), node.position) {
+            return Err(anyhow!("Tasks {} and {} both have the Id \"{}\", Ids have to be unique.", a + 1, node.position + 1, id))
+        }
+        if groups.contains_key(id) {
+            return Err(anyhow!("Two tasks have the Id \"{}\", Ids have to be unique.", id))
+        }
+    }
+
+    // Outputs and facts of a task that runs several times have to name the invocation they mean.
+    let single = |id: &str| -> Result<Option<usize>> {
+        match groups.get(id) {
+            Some(_) => Err(anyhow!("task \"{}\", which runs once per item, so name the one you mean, e.g. \"{}-1\"", id, id)),
+            None => Ok(ids.get(id).copied())
+        }
+    };
+
+    for (node, needs) in nodes.iter_mut().zip(explicit_needs) {
+        let needs = match needs { Some(a) => a, None => continue };
+        node.after.clear();
+
+        for need in needs {
+            match (ids.get(&need), groups.get(&need)) {
+                (Some(a), _) => node.needs.push(*a),
+                (None, Some(a)) => node.needs.extend(a),
+                (None, None) => return Err(anyhow!("Task \"{}\" needs \"{}\", but no task has that Id.", node.label(), need))
+            }
+        }
+    }
+
+    for (node, refs) in nodes.iter_mut().zip(output_refs) {
+        for task_ref in refs {
+            match single(&task_ref).map_err(|e| anyhow!("Task \"{}\" uses the outputs of {}.", node.label(), e))? {
+                Some(a) => if !node.needs.contains(&a) { node.needs.push(a) },
+                None => return Err(anyhow!("Task \"{}\" uses the outputs of task \"{}\", but no task has that Id.", node.label(), task_ref))
+            }
+        }
+    }
+
+    for node in nodes.iter_mut() {
+        let refs = match &node.when { Some(a) => a.expr.task_refs(), None => continue };
+        for task_ref in refs {
+            match single(&task_ref).map_err(|e| anyhow!("The When of task \"{}\" looks at {}.", node.label(), e))? {
+                Some(a) => if !node.after.contains(&a) { node.after.push(a) },
+                None => return Err(anyhow!("The When of task \"{}\" looks at task \"{}\", but no task has that Id.", node.label(), task_ref))
+            }
+        }
+    }
+
+    if let Some(cycle) = find_cycle(&nodes) {
+        let labels: Vec<String> = cycle.iter().map(|a| format!("\"{}\"", nodes[*a].label())).collect();
+        return Err(anyhow!("Tasks wait for each other in a cycle, so none of them could ever start: {} (each waits for the next).", labels.join(" -> ")))
+    }
+
+    Ok(nodes)
+}
+
+/// Returns the positions of the tasks in a cycle of tasks waiting for each other, starting and ending at the same task,
+/// if there is one.
+fn find_cycle(nodes: &[TaskNode]) -> Option<Vec<usize>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark { Unvisited, InPath, Done }
+
+    fn visit(position: usize, nodes: &[TaskNode], marks: &mut [Mark], path: &mut Vec<usize>) -> Option<Vec<usize>> {
+        marks[position] = Mark::InPath;
+        path.push(position);
+
+        for need in nodes[position].needs.iter().chain(&nodes[position].after) {
+            match marks[*need] {
+                Mark::Done => continue,
+                Mark::InPath => {
+                    let start = path.iter().position(|a| a == need)?;
+                    let mut cycle = path[start..].to_vec();
+                    cycle.push(*need);
+                    return Some(cycle)
+                },
+                Mark::Unvisited => {
+                    if let Some(a) = visit(*need, nodes, marks, path) { return Some(a) }
+                }
+            }
+        }
+
+        path.pop();
+        marks[position] = Mark::Done;
+        None
+    }
+
+    let mut marks = vec![Mark::Unvisited; nodes.len()];
+    for position in 0..nodes.len() {
+        if marks[position] != Mark::Unvisited { continue }
+        if let Some(a) = visit(position, nodes, &mut marks, &mut Vec::new()) { return Some(a) }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds the graph of a task list written as YAML, with sanitized keys as a run would have them.
+    fn graph(yaml: &str) -> Result<Vec<TaskNode>> {
+        let entries: Vec<Value> = serde_yml::from_str(yaml).unwrap();
+        build_task_graph(&entries, &Variables::default())
+    }
+
+    fn error(yaml: &str) -> String {
+        graph(yaml).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn tasks_wait_for_the_one_before_them() {
+        let nodes = graph("[{a: {}}, {b: {}}, {c: {}}]").unwrap();
+        let after: Vec<Vec<usize>> = nodes.iter().map(|a| a.after.clone()).collect();
+        assert_eq!(after, vec![vec![], vec![0], vec![1]]);
+        assert!(nodes.iter().all(|a| a.needs.is_empty()));
+    }
+
+    #[test]
+    fn parallel_blocks_run_side_by_side() {
+        let nodes = graph("[{a: {}}, {parallel: [{b: {}}, {c: {}}]}, {d: {}}]").unwrap();
+        assert_eq!(nodes[1].after, vec![0]);
+        assert_eq!(nodes[2].after, vec![0]);
+        assert_eq!(nodes[3].after, vec![1, 2]);
+    }
+
+    #[test]
+    fn nested_parallel_blocks_are_rejected() {
+        assert!(error("[{parallel: [{parallel: [{a: {}}]}]}]").contains("nested"));
+        assert!(error("[{parallel: 3}]").contains("list of tasks"));
+    }
+
+    #[test]
+    fn needs_replace_the_order_of_the_list() {
+        let nodes = graph("[{a: {id: first}}, {b: {}}, {c: {needs: first}}, {d: {}}]").unwrap();
+        assert_eq!(nodes[2].needs, vec![0]);
+        assert!(nodes[2].after.is_empty());
+        // A task after one with Needs still waits for it in list order.
+        assert_eq!(nodes[3].after, vec![2]);
+        assert!(nodes[3].needs.is_empty());
+    }
+
+    #[test]
+    fn when_and_outputs_add_what_they_look_at() {
+        let nodes = graph(r#"
+            - a: {id: probe}
+            - b: {id: other}
+            - c: {when: "tasks.probe.failed", needs: []}
+            - d: {value: "${tasks.other.outputs.stdout}", needs: []}
+        "#).unwrap();
+        assert_eq!((nodes[2].needs.clone(), nodes[2].after.clone()), (vec![], vec![0]));
+        assert_eq!((nodes[3].needs.clone(), nodes[3].after.clone()), (vec![1], vec![]));
+    }
+
+    #[test]
+    fn unknown_and_duplicate_ids_are_rejected() {
+        assert!(error("[{a: {needs: nope}}]").contains("no task has that Id"));
+        assert!(error("[{a: {when: 'tasks.nope.failed'}}]").contains("no task has that Id"));
+        assert!(error("[{a: {value: '${tasks.nope.outputs.x}'}}]").contains("no task has that Id"));
+        assert!(error("[{a: {id: x}}, {b: {id: x}}]").contains("unique"));
+        assert!(error("[{a: {needs: [1]}}]").contains("only hold task Ids"));
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let message = error("[{a: {id: x, needs: y}}, {b: {id: y, needs: x}}]");
+        assert!(message.contains("cycle"), "{}", message);
+        assert!(message.contains("\"x\" -> \"y\" -> \"x\""), "{}", message);
+
+        assert!(error("[{a: {id: x, needs: x}}]").contains("cycle"));
+        // A task waiting in list order for one that needs it.
+        assert!(error("[{a: {id: x, needs: y}}, {b: {id: y}}]").contains("cycle"));
+    }
+
+    #[test]
+    fn find_cycle_returns_the_loop() {
+        let node = |position: usize, needs: Vec<usize>| TaskNode {
+            position,
+            user_task: Value::Null,
+            id: None,
+            needs,
+            after: Vec::new(),
+            when: None,
+            bindings: Vec::new()
+        };
+
+        assert_eq!(find_cycle(&[node(0, vec![]), node(1, vec![0])]), None);
+        assert_eq!(find_cycle(&[node(0, vec![1]), node(1, vec![2]), node(2, vec![1])]), Some(vec![1, 2, 1]));
+    }
+
+    #[test]
+    fn for_each_expands_into_numbered_invocations() {
+        let nodes = graph("[{a: {id: x, foreach: [1, 2]}}, {b: {needs: x}}]").unwrap();
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(nodes[0].id.as_deref(), Some("x-1"));
+        assert_eq!(nodes[1].id.as_deref(), Some("x-2"));
+        assert_eq!(nodes[1].after, vec![0]);
+        assert_eq!(nodes[2].needs, vec![0, 1]);
+        assert_eq!(nodes[1].binding("ITEM"), Some(Value::from(2)));
+
+        assert!(error("[{a: {id: x, foreach: [1]}}, {b: {when: 'tasks.x.failed'}}]").contains("once per item"));
+        assert!(error("[{a: {foreach: [1], matrix: {os: [a]}}}]").contains("only have one"));
+    }
+}
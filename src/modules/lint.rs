@@ -0,0 +1,300 @@
+use anyhow::{Result, anyhow};
+use serde_yml::{Mapping, Value};
+
+use std::fs::read_dir;
+
+use crate::modules::registry::tasks_dir;
+use crate::modules::schema::{check_definition, describe, inline, meta};
+use crate::modules::shell::script_extensions;
+use crate::modules::task::{get_by_path, get_task_script, task_parameter_problems};
+use crate::modules::utils::{sanitize_string, sanitize_yaml};
+
+/// Checks the task definitions of tasks.yaml, and the scripts of the tasks folder, for mistakes that runs trip over
+/// or never notice. Returns every problem found.
+pub fn lint_definitions(task_input: &Value) -> Result<Vec<String>> {
+    let tasks = task_input.as_mapping().ok_or(anyhow!("The task definitions are not a map of task names to definitions."))?;
+
+    let mut problems = Vec::new();
+    let mut task_names: Vec<(String, String)> = Vec::new();
+    // Names of the scripts the definitions use, with neither the folder nor the extension.
+    let mut scripts: Vec<String> = Vec::new();
+
+    for (key, definition) in tasks {
+        let name = match key.as_str() {
+            Some(a) => a,
+            None => {
+                problems.push(format!("there is a task named {}, which is not text", inline(key)));
+                continue
+            }
+        };
+        let label = format!("task \"{}\"", name);
+        let definition = match definition.as_mapping() {
+            Some(a) => a,
+            None => {
+                problems.push(format!("{} is not a map of parameters and such", label));
+                continue
+            }
+        };
+
+        for a in names_of(name, definition, &label, &mut problems) { task_names.push((a, name.to_owned())) }
+
+        let mut parameter_names: Vec<(String, String)> = Vec::new();
+        for (a, b) in definition {
+            let (parameter, spec) = match (a.as_str(), b.as_mapping()) {
+                (Some(c), Some(d)) if !c.starts_with('+') => (c, d),
+                _ => continue
+            };
+            let parameter_label = format!("{}: parameter \"{}\"", label, parameter);
+            for c in names_of(parameter, spec, &parameter_label, &mut problems) { parameter_names.push((c, parameter.to_owned())) }
+        }
+        for (a, b) in collisions(&parameter_names) {
+            problems.push(format!("{}: parameters {} each answer to \"{}\"", label, b, a))
+        }
+
+        check_passthru(task_input, name, definition, &mut problems);
+        check_example(task_input, name, definition, &mut problems);
+        for a in check_definition(&Value::Mapping(definition.to_owned())) { problems.push(format!("{}: {}", label, a)) }
+
+        if !get_task_script(name, name, None)?.exists() {
+            problems.push(format!(
+                "{} has no script, which should be \"{}\" ending in .{}, in the tasks folder or the task's own folder",
+                label, name, script_extensions().join(", .")
+            ));
+        }
+        scripts.push(name.to_owned());
+
+        match meta(definition, "undo") {
+            None | Some(Value::Null) => {},
+            Some(Value::String(a)) => {
+                if !get_task_script(a, name, None)?.exists() {
+                    problems.push(format!("{} has \"+Undo: {}\", but there is no script of that name", label, a));
+                }
+                scripts.push(a.to_owned());
+            },
+            Some(a) => problems.push(format!("{} has \"+Undo: {}\", which should name a script in the tasks folder", label, inline(a)))
+        }
+    }
+
+    for (a, b) in collisions(&task_names) {
+        problems.push(format!("tasks {} each answer to \"{}\"", b, a))
+    }
+
+    for a in script_files()? {
+        let stem = script_extensions().iter().find_map(|b| a.strip_suffix(&format!(".{}", b)));
+        if let Some(b) = stem && !scripts.iter().any(|c| c == b) {
+            problems.push(format!("script \"tasks/{}\" has no definition", a));
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Returns the sanitized names an entry of tasks.yaml answers to, its own and those of its "+Aliases", adding a
+/// problem for every alias that is empty or not text.
+fn names_of(name: &str, entry: &Mapping, label: &str, problems: &mut Vec<String>) -> Vec<String> {
+    let mut names = vec![sanitize_string(name)];
+
+    match meta(entry, "aliases") {
+        None => {},
+        Some(Value::Sequence(a)) => for b in a {
+            match b.as_str().map(sanitize_string) {
+                Some(c) if !c.is_empty() => if !names.contains(&c) { names.push(c) },
+                Some(_) => problems.push(format!("{} has the alias \"{}\", which is empty once numbers and symbols are left out", label, inline(b))),
+                None if b.is_null() => problems.push(format!("{} has an empty alias", label)),
+                None => problems.push(format!("{} has the alias {}, which is not text", label, inline(b)))
+            }
+        },
+        Some(a) => problems.push(format!("{} has \"+Aliases: {}\", which should be a list of names", label, inline(a)))
+    }
+
+    names
+}
+
+/// Returns every name that more than one owner answers to, along with those owners, e.g. `"A" and "B"`.
+fn collisions(names: &[(String, String)]) -> Vec<(String, String)> {
+    let mut seen: Vec<&str> = Vec::new();
+    let mut found = Vec::new();
+
+    for (name, _) in names {
+        if seen.contains(&name.as_str()) { continue }
+        seen.push(name);
+
+        let mut owners: Vec<String> = Vec::new();
+        for (_, a) in names.iter().filter(|(b, _)| b == name) {
+            let quoted = format!("\"{}\"", a);
+            if !owners.contains(&quoted) { owners.push(quoted) }
+        }
+
+        if let Some((a, b)) = owners.split_last() && !b.is_empty() {
+            found.push((name.to_owned(), format!("{} and {}", b.join(", "), a)));
+        }
+    }
+
+    found
+}
+
+/// Checks that every entry of a definition's "+Passthru" is a path to one of its own parameters, under its name.
+fn check_passthru(task_input: &Value, name: &str, definition: &Mapping, problems: &mut Vec<String>) {
+    let label = format!("task \"{}\"", name);
+    let passthru = match meta(definition, "passthru") {
+        Some(Value::Mapping(a)) => a,
+        None => return problems.push(format!("{} has no \"+Passthru\", so it can't be run", label)),
+        Some(a) => return problems.push(format!("{} has \"+Passthru: {}\", which should map names to paths like \"{}/Param\"", label, inline(a), name))
+    };
+    names_of("passthru", passthru, &format!("{}: \"+Passthru\"", label), problems);
+
+    let own = Value::Mapping(definition.to_owned());
+    for (key, path) in passthru {
+        let key = match key.as_str() {
+            Some(a) if a.starts_with('+') => continue,
+            Some(a) => a,
+            None => {
+                problems.push(format!("{} passes through {}, which is not text", label, inline(key)));
+                continue
+            }
+        };
+        let path = match path.as_str().filter(|a| a.split('/').count() == 2) {
+            Some(a) => a,
+            None => {
+                problems.push(format!("{} passes \"{}\" through from {}, which should be a path like \"{}/{}\"", label, key, inline(path), name, key));
+                continue
+            }
+        };
+
+        let parameter = match get_by_path(task_input, path, None) {
+            Some((_, Value::String(a))) if !a.starts_with('+') => a,
+            _ => {
+                problems.push(format!("{} passes \"{}\" through from \"{}\", which is not a parameter of any task", label, key, path));
+                continue
+            }
+        };
+
+        let owner = path.split('/').next().unwrap_or_default();
+        if let Some((_, Value::String(a))) = get_by_path(task_input, &format!("{}/", owner), None) && a != name {
+            problems.push(format!("{} passes \"{}\" through from \"{}\", which belongs to task \"{}\"", label, key, path, a));
+            continue
+        }
+
+        let declared = get_by_path(&own, &format!("{}/", key), None).and_then(|(_, a)| a.as_str().map(|b| b.to_owned()));
+        if declared.as_deref() != Some(parameter.as_str()) {
+            problems.push(format!(
+                "{} passes through \"{}\", which is neither the name nor an alias of the parameter \"{}\" it comes from", label, key, parameter
+            ));
+        }
+    }
+}
+
+/// Checks that the tasks a definition's "+Example" runs are the task itself, with parameters that fit its definition.
+fn check_example(task_input: &Value, name: &str, definition: &Mapping, problems: &mut Vec<String>) {
+    let label = format!("task \"{}\"", name);
+    let example = match meta(definition, "example").and_then(|a| a.as_str()) {
+        Some(a) => a,
+        None => return
+    };
+    let example: Value = match serde_yml::from_str(example) {
+        Ok(a) => a,
+        Err(e) => return problems.push(format!("{} has an \"+Example\" that is not valid YAML: {}", label, e))
+    };
+
+    let tasks = match get_by_path(&example, "tasks", None) {
+        Some((Value::Sequence(a), _)) => a,
+        Some((a, _)) => return problems.push(format!("{} has an \"+Example\" whose \"Tasks\" is {}, not a list of tasks", label, describe(&a))),
+        None => return problems.push(format!("{} has an \"+Example\" without a \"Tasks\" list", label))
+    };
+    let task_data = sanitize_yaml(&Value::Mapping(definition.to_owned())).unwrap_or_default();
+    for task in tasks {
+        let (key, data) = match task.as_mapping().and_then(|a| a.iter().next()) {
+            Some((a, b)) => match a.as_str() {
+                Some(c) if sanitize_string(c) != "parallel" => (c.to_owned(), b),
+                _ => continue
+            },
+            None => continue
+        };
+
+        match get_by_path(task_input, &format!("{}/", key), None) {
+            Some((_, Value::String(a))) if a == name => {},
+            Some((_, Value::String(a))) => {
+                problems.push(format!("{} has an \"+Example\" that runs task \"{}\" instead", label, a));
+                continue
+            },
+            _ => {
+                problems.push(format!("{} has an \"+Example\" that runs \"{}\", which is not a task", label, key));
+                continue
+            }
+        }
+
+        let user_data = sanitize_yaml(data).unwrap_or_default();
+        for a in task_parameter_problems(&user_data, &task_data, &Value::Mapping(definition.to_owned())) {
+            problems.push(format!("{} has an \"+Example\" whose {}", label, a));
+        }
+    }
+}
+
+/// Names of the files in the tasks folder that are scripts of some shell, sorted.
+///
+/// The folders of tasks with a "task.yaml" of their own are left out, as scripts there may be files a task comes with.
+fn script_files() -> Result<Vec<String>> {
+    let tasks_dir = tasks_dir()?;
+
+    let mut files: Vec<String> = read_dir(&tasks_dir)
+        .map_err(|e| anyhow!("Could not read the tasks folder \"{}\": {}", tasks_dir.display(), e))?
+        .filter_map(|a| a.ok())
+        .filter(|a| a.path().is_file())
+        .filter_map(|a| a.file_name().to_str().map(|b| b.to_owned()))
+        .filter(|a| script_extensions().iter().any(|b| a.ends_with(&format!(".{}", b))))
+        .collect();
+    files.sort();
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Problems *check_example()* finds with the "+Example" of a task "Greet" with a required text parameter "Who".
+    fn example_problems(example: &str) -> Vec<String> {
+        let mut task_input: Value = serde_yml::from_str(r#"
+            Greet:
+              +Aliases: [Hello]
+              Who:
+                +Type: string
+                +Required: true
+                +Aliases: [Name]
+            Other: {}
+        "#).unwrap();
+        task_input["Greet"]["+Example"] = Value::String(example.to_owned());
+
+        let mut problems = Vec::new();
+        check_example(&task_input, "Greet", task_input["Greet"].as_mapping().unwrap(), &mut problems);
+        problems
+    }
+
+    #[test]
+    fn good_examples_pass() {
+        assert!(example_problems("Tasks:\n- Greet:\n    Who: World").is_empty());
+        assert!(example_problems("Tasks:\n- Hello:\n    Name: World").is_empty());
+    }
+
+    #[test]
+    fn examples_of_other_tasks_are_reported() {
+        let problems = example_problems("Tasks:\n- Other: {}");
+        assert_eq!(problems, vec!["task \"Greet\" has an \"+Example\" that runs task \"Other\" instead"]);
+        assert_eq!(example_problems("Tasks:\n- Nope: {}").len(), 1);
+    }
+
+    #[test]
+    fn example_parameters_are_checked() {
+        let problems = example_problems("Tasks:\n- Greet:\n    Who: [a, b]");
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("parameter \"Who\" should be text"), "{:?}", problems);
+        assert!(example_problems("Tasks:\n- Greet: {}")[0].contains("is required"));
+    }
+
+    #[test]
+    fn examples_without_a_list_of_tasks_are_reported() {
+        assert!(example_problems("Tasks:\n  Greet:\n    Who: World")[0].contains("is a map, not a list of tasks"));
+        assert!(example_problems("Greet:\n  Who: World")[0].contains("without a \"Tasks\" list"));
+        assert!(example_problems("Tasks: [")[0].contains("not valid YAML"));
+    }
+}
@@ -1,5 +1,14 @@
 pub mod docs;
 pub mod task;
+pub mod graph;
+pub mod expr;
+pub mod vars;
+pub mod state;
+pub mod schema;
+pub mod lint;
+pub mod registry;
+pub mod shell;
+pub mod report;
 pub mod utils;
 pub mod cli;
 // pub mod windows;
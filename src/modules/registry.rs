@@ -0,0 +1,167 @@
+use anyhow::{Result, anyhow};
+use serde_yml::{Mapping, Value};
+
+use std::env;
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+
+use crate::modules::schema::inline;
+use crate::modules::utils::{file_contents, sanitize_string};
+
+/// File of the tasks folder that defines many tasks at once.
+const TASKS_FILE: &str = "tasks.yaml";
+/// File of a task's own folder, e.g. "tasks/MapDrive/task.yaml", that defines the task named after the folder.
+const TASK_FILE: &str = "task.yaml";
+
+/// Gets the location of the tasks folder relative to the current working directory.
+pub fn tasks_dir() -> Result<PathBuf> {
+    Ok(env::current_dir()?.join("tasks"))
+}
+
+/// Folders of a tasks folder that hold a task of their own, i.e. a "task.yaml", sorted by name.
+pub fn task_folders(tasks_dir: &Path) -> Result<Vec<PathBuf>> {
+    if !tasks_dir.is_dir() { return Ok(Vec::new()) }
+
+    let mut folders: Vec<PathBuf> = read_dir(tasks_dir)
+        .map_err(|e| anyhow!("Could not read the tasks folder \"{}\": {}", tasks_dir.display(), e))?
+        .filter_map(|a| a.ok())
+        .map(|a| a.path())
+        .filter(|a| a.join(TASK_FILE).is_file())
+        .collect();
+    folders.sort();
+
+    Ok(folders)
+}
+
+/// Reads a YAML file of task definitions, where an empty file defines nothing.
+async fn read_definitions(path: &PathBuf) -> Result<Value> {
+    let contents = file_contents(path).await
+        .map_err(|e| anyhow!("Could not read \"{}\": {}", display_path(path), e))?;
+
+    serde_yml::from_str(&contents).map_err(|e| anyhow!("Could not parse \"{}\": {}", display_path(path), e))
+}
+
+/// A path relative to the working directory where possible, for messages.
+fn display_path(path: &Path) -> String {
+    let relative = env::current_dir().ok().and_then(|a| path.strip_prefix(a).ok().map(|b| b.to_path_buf()));
+    relative.unwrap_or(path.to_path_buf()).display().to_string()
+}
+
+/// Loads every task definition into one map, from "tasks/tasks.yaml" and the "task.yaml" of every task folder.
+///
+/// Names are compared once sanitized, as that is how tasks are looked up, and a task defined more than once is an
+/// error naming every file that defines it.
+pub async fn load_task_definitions() -> Result<Value> {
+    load_definitions_from(&tasks_dir()?).await
+}
+
+/// Loads every task definition of a given tasks folder, see *load_task_definitions()*.
+async fn load_definitions_from(tasks_dir: &Path) -> Result<Value> {
+    let mut definitions = Mapping::new();
+    // Sanitized name, name and file of every definition, to find the names defined more than once.
+    let mut sources: Vec<(String, String, String)> = Vec::new();
+
+    let tasks_file = tasks_dir.join(TASKS_FILE);
+    if tasks_file.is_file() {
+        match read_definitions(&tasks_file).await? {
+            Value::Null => {},
+            Value::Mapping(a) => for (key, definition) in a {
+                let name = key.as_str().ok_or(anyhow!("\"{}\" has a task named {}, which is not text.", display_path(&tasks_file), inline(&key)))?;
+                sources.push((sanitize_string(name), name.to_owned(), display_path(&tasks_file)));
+                definitions.insert(key, definition);
+            },
+            _ => return Err(anyhow!("\"{}\" should be a map of task names to their definitions.", display_path(&tasks_file)))
+        }
+    }
+
+    for folder in task_folders(tasks_dir)? {
+        let task_file = folder.join(TASK_FILE);
+        let name = folder.file_name().and_then(|a| a.to_str())
+            .ok_or(anyhow!("The name of the task folder \"{}\" is not valid text.", display_path(&folder)))?;
+
+        let definition = read_definitions(&task_file).await?;
+        if !definition.is_mapping() {
+            return Err(anyhow!("\"{}\" should be a map of the parameters and such of task \"{}\".", display_path(&task_file), name))
+        }
+
+        sources.push((sanitize_string(name), name.to_owned(), display_path(&task_file)));
+        definitions.insert(Value::String(name.to_owned()), definition);
+    }
+
+    let mut duplicates: Vec<String> = Vec::new();
+    for (i, (sanitized, name, _)) in sources.iter().enumerate() {
+        if sources[..i].iter().any(|(a, _, _)| a == sanitized) { continue }
+
+        let defined_in: Vec<String> = sources.iter()
+            .filter(|(a, _, _)| a == sanitized)
+            .map(|(_, b, c)| format!("\"{}\" in \"{}\"", b, c))
+            .collect();
+        if defined_in.len() > 1 {
+            duplicates.push(format!("task \"{}\" is defined {} times: {}", name, defined_in.len(), defined_in.join(", ")));
+        }
+    }
+
+    if !duplicates.is_empty() {
+        return Err(anyhow!(
+            "Every task needs a name of its own, but {} name(s) are taken more than once:\n  {}\nNames are compared without case, numbers or symbols.",
+            duplicates.len(), duplicates.join("\n  ")
+        ))
+    }
+
+    if definitions.is_empty() {
+        return Err(anyhow!(
+            "No tasks are defined. Define them in \"{}\", or give a task a folder of its own with a \"{}\" such as \"{}\".",
+            display_path(&tasks_file), TASK_FILE, display_path(&tasks_dir.join("MyTask").join(TASK_FILE))
+        ))
+    }
+
+    Ok(Value::Mapping(definitions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes files into a fresh tasks folder, e.g. `("MapDrive/task.yaml", "Letter: {}")`.
+    fn tasks_folder(files: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("tm-registry-{}", uuid::Uuid::new_v4()));
+        for (name, contents) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+
+        dir
+    }
+
+    async fn load(files: &[(&str, &str)]) -> Result<Value> {
+        let dir = tasks_folder(files);
+        let definitions = load_definitions_from(&dir).await;
+        std::fs::remove_dir_all(dir).unwrap();
+        definitions
+    }
+
+    #[tokio::test]
+    async fn folders_are_merged_with_tasks_yaml() {
+        let definitions = load(&[("tasks.yaml", "ExecBash: {Script: {}}"), ("MapDrive/task.yaml", "Letter: {}"), ("Empty/README.md", "")]).await.unwrap();
+        let names: Vec<&str> = definitions.as_mapping().unwrap().keys().filter_map(|a| a.as_str()).collect();
+        assert_eq!(names, vec!["ExecBash", "MapDrive"]);
+    }
+
+    #[tokio::test]
+    async fn names_taken_twice_are_errors() {
+        let e = load(&[("tasks.yaml", "Map-Drive: {}\nExecBash: {}"), ("mapdrive/task.yaml", "Letter: {}")]).await.unwrap_err().to_string();
+        assert!(e.contains("1 name(s) are taken more than once"), "{}", e);
+        assert!(e.contains("task \"Map-Drive\" is defined 2 times: \"Map-Drive\" in"), "{}", e);
+        assert!(e.contains("\"mapdrive\" in"), "{}", e);
+
+        let e = load(&[("tasks.yaml", "ExecBash: {}\nexec_bash: {}")]).await.unwrap_err().to_string();
+        assert!(e.contains("task \"ExecBash\" is defined 2 times"), "{}", e);
+    }
+
+    #[tokio::test]
+    async fn no_tasks_is_an_error() {
+        let e = load(&[("tasks.yaml", "")]).await.unwrap_err().to_string();
+        assert!(e.starts_with("No tasks are defined."), "{}", e);
+    }
+}
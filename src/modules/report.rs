@@ -0,0 +1,724 @@
+use anyhow::{Result, anyhow};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use warp::Filter;
+use warp::http::StatusCode;
+
+use uuid::Uuid;
+
+use chrono::{DateTime, Local};
+
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration
+};
+
+use log::{Level, error, info, log, warn};
+
+use crate::modules::shell::TaskOutcome;
+use crate::modules::utils::{create_file, ensure_dir};
+
+/// How long a task's route keeps accepting its report after the task's process has exited.
+pub const REPORT_GRACE: Duration = Duration::from_secs(5);
+
+/// Version of the report schema this build of Taskmaster understands.
+pub const REPORT_VERSION: u32 = 1;
+
+/// Overall result of a task, as judged by the task itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportStatus {
+    /// Everything the task set out to do was done.
+    Success,
+    /// The task finished, but something needs a human to look at it. Counts as a success.
+    Warning,
+    /// The task could not do what it set out to do.
+    Failure
+}
+
+impl fmt::Display for ReportStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReportStatus::Success => write!(f, "success"),
+            ReportStatus::Warning => write!(f, "warning"),
+            ReportStatus::Failure => write!(f, "failure")
+        }
+    }
+}
+
+/// Severity of a report message or a streamed log line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageLevel {
+    Trace,
+    #[serde(alias = "verbose")]
+    Debug,
+    #[serde(alias = "information")]
+    Info,
+    #[serde(alias = "warning")]
+    Warn,
+    Error
+}
+
+impl MessageLevel {
+    /// The matching level of the log crate.
+    pub fn log_level(&self) -> Level {
+        match self {
+            MessageLevel::Trace => Level::Trace,
+            MessageLevel::Debug => Level::Debug,
+            MessageLevel::Info => Level::Info,
+            MessageLevel::Warn => Level::Warn,
+            MessageLevel::Error => Level::Error
+        }
+    }
+}
+
+/// A leveled line of text in a report.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReportMessage {
+    #[serde(default = "default_message_level")]
+    pub level: MessageLevel,
+    pub text: String
+}
+
+fn default_message_level() -> MessageLevel {
+    MessageLevel::Info
+}
+
+/// The report a task posts once it is done.
+///
+/// # Example
+/// ```json
+/// {
+///     "version": 1,
+///     "status": "success",
+///     "messages": [{"level": "info", "text": "Mapped drive letter Y to path \\\\server\\share."}],
+///     "changed": ["Y: -> \\\\server\\share"],
+///     "unchanged": [],
+///     "outputs": {"mappedCount": 1},
+///     "artifacts": []
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Report {
+    /// Schema version, must be *REPORT_VERSION*.
+    pub version: u32,
+    pub status: ReportStatus,
+    #[serde(default)]
+    pub messages: Vec<ReportMessage>,
+    /// Items the task changed on the machine.
+    #[serde(default)]
+    pub changed: Vec<String>,
+    /// Items the task found already in the desired state.
+    #[serde(default)]
+    pub unchanged: Vec<String>,
+    /// Named values produced by the task.
+    #[serde(default)]
+    pub outputs: Map<String, Value>,
+    /// Paths of files the task produced, such as logs or exports.
+    #[serde(default)]
+    pub artifacts: Vec<PathBuf>
+}
+
+/// Checks a posted JSON body against the report schema.
+pub fn parse_report(value: Value) -> Result<Report> {
+    if !value.is_object() {
+        return Err(anyhow!("A report must be a JSON object with at least \"version\" and \"status\", not {}.", value))
+    }
+
+    let version = value.get("version").and_then(|a| a.as_u64());
+    if version.is_some_and(|a| a != REPORT_VERSION as u64) {
+        return Err(anyhow!("Unsupported report version {}, this version of Taskmaster expects version {}.", version.unwrap_or_default(), REPORT_VERSION))
+    }
+
+    serde_json::from_value::<Report>(value).map_err(|e| anyhow!(e))
+}
+
+/// Where the reports and events of a registered task go.
+#[derive(Clone)]
+struct TaskRoute {
+    /// Task name shown alongside its progress and log events.
+    name: String,
+    tx: Sender<Report>
+}
+
+/// State shared between every request handler of the report server.
+struct ServerState {
+    /// Routes of every task currently waiting on a report, keyed by task ID.
+    routes: Mutex<HashMap<String, TaskRoute>>,
+    expected_auth: String
+}
+
+type Reply = warp::reply::WithStatus<String>;
+
+/// Checks the bearer token of a request and finds the route of its task ID, or the reply to reject it with.
+fn authorize(state: &ServerState, task_id: &str, auth: Option<String>) -> Result<TaskRoute, Reply> {
+    if auth.as_deref() != Some(state.expected_auth.as_str()) {
+        return Err(warp::reply::with_status("Missing or invalid report token.".to_owned(), StatusCode::UNAUTHORIZED))
+    }
+
+    match state.routes.lock().ok().and_then(|a| a.get(task_id).cloned()) {
+        Some(a) => Ok(a),
+        None => {
+            warn!("Received a post for unknown or finished task \"{}\", rejecting it.", task_id);
+            Err(warp::reply::with_status("Unknown task ID.".to_owned(), StatusCode::NOT_FOUND))
+        }
+    }
+}
+
+/// Handles the final report of a task, passing it on to the task's channel once it is checked against the schema.
+async fn handle_report(task_id: String, auth: Option<String>, value: Value, state: Arc<ServerState>) -> Result<Reply, warp::Rejection> {
+    let route = match authorize(&state, &task_id, auth) {
+        Ok(a) => a,
+        Err(e) => return Ok(e)
+    };
+
+    let report = match parse_report(value) {
+        Ok(a) => a,
+        Err(e) => {
+            warn!("[{}] Rejected an invalid report: {}", route.name, e);
+            return Ok(warp::reply::with_status(format!("Invalid report: {}", e), StatusCode::BAD_REQUEST))
+        }
+    };
+
+    match route.tx.send(report).await {
+        Ok(_) => Ok(warp::reply::with_status("Report received.".to_owned(), StatusCode::OK)),
+        Err(_) => Ok(warp::reply::with_status("Error occured while processing report.".to_owned(), StatusCode::INTERNAL_SERVER_ERROR))
+    }
+}
+
+/// Handles a progress update of a running task, e.g. {"percent": 40, "status": "Copying files"}.
+async fn handle_progress(task_id: String, auth: Option<String>, value: Value, state: Arc<ServerState>) -> Result<Reply, warp::Rejection> {
+    let route = match authorize(&state, &task_id, auth) {
+        Ok(a) => a,
+        Err(e) => return Ok(e)
+    };
+
+    let percent = value.get("percent").and_then(|a| a.as_f64());
+    let status = value.get("status").and_then(|a| a.as_str());
+
+    let line = match (percent, status) {
+        (Some(a), Some(b)) => format!("{:.0}% - {}", a.clamp(0.0, 100.0), b),
+        (Some(a), None) => format!("{:.0}%", a.clamp(0.0, 100.0)),
+        (None, Some(b)) => b.to_owned(),
+        (None, None) => {
+            return Ok(warp::reply::with_status(
+                "A progress update needs a numeric \"percent\" and/or a string \"status\".".to_owned(),
+                StatusCode::BAD_REQUEST
+            ))
+        }
+    };
+
+    info!("[{}] {}", route.name, line);
+    Ok(warp::reply::with_status("Progress received.".to_owned(), StatusCode::OK))
+}
+
+/// Handles a log line of a running task, e.g. {"level": "warn", "message": "Retrying download"}.
+async fn handle_log(task_id: String, auth: Option<String>, value: Value, state: Arc<ServerState>) -> Result<Reply, warp::Rejection> {
+    let route = match authorize(&state, &task_id, auth) {
+        Ok(a) => a,
+        Err(e) => return Ok(e)
+    };
+
+    let message = match value.get("message").and_then(|a| a.as_str()) {
+        Some(a) => a,
+        None => {
+            return Ok(warp::reply::with_status("A log line needs a string \"message\".".to_owned(), StatusCode::BAD_REQUEST))
+        }
+    };
+
+    let level = match value.get("level").map(|a| serde_json::from_value::<MessageLevel>(a.to_owned())) {
+        None => MessageLevel::Info,
+        Some(Ok(a)) => a,
+        Some(Err(_)) => {
+            return Ok(warp::reply::with_status(
+                format!("Unknown log level {}, expected one of trace, debug, info, warn or error.", value["level"]),
+                StatusCode::BAD_REQUEST
+            ))
+        }
+    };
+
+    log!(level.log_level(), "[{}] {}", route.name, message);
+    Ok(warp::reply::with_status("Log received.".to_owned(), StatusCode::OK))
+}
+
+/// A single http server on an ephemeral localhost port that receives the reports and events of every task in a run.
+///
+/// All posts are JSON, carry the run's bearer token, and are routed by task ID:
+/// - "{uri}/{task ID}" is the task's final report, passed on to the channel registered for that task ID.
+/// - "{uri}/{task ID}/progress" is a progress update, shown and logged as it arrives.
+/// - "{uri}/{task ID}/log" is a log line, shown and logged as it arrives.
+///
+/// Posts without the token, or for a task ID that is not registered, are rejected.
+pub struct ReportServer {
+    /// Base URI of the server.
+    pub uri: String,
+    /// Random token every post must carry as "Authorization: Bearer {token}".
+    pub token: String,
+    state: Arc<ServerState>,
+    shutdown_tx: oneshot::Sender<()>,
+    handle: JoinHandle<()>
+}
+
+impl ReportServer {
+    /// Binds the server to an ephemeral port on localhost with a fresh token, and starts serving.
+    pub async fn start() -> Result<ReportServer> {
+        let token = Uuid::new_v4().simple().to_string();
+        let state = Arc::new(ServerState {
+            routes: Mutex::new(HashMap::new()),
+            expected_auth: format!("Bearer {}", token)
+        });
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+        let shared_state = state.clone();
+        let with_state = warp::any().map(move || shared_state.clone());
+        let post = warp::post().and(warp::path::param::<String>());
+        let body = warp::header::optional::<String>("authorization")
+            .and(warp::body::json())
+            .and(with_state);
+
+        let report = post.and(warp::path::end()).and(body.clone()).and_then(handle_report);
+        let progress = post.and(warp::path("progress")).and(warp::path::end()).and(body.clone()).and_then(handle_progress);
+        let log = post.and(warp::path("log")).and(warp::path::end()).and(body).and_then(handle_log);
+
+        let (addr, server) = warp::serve(report.or(progress).or(log))
+            .try_bind_with_graceful_shutdown(([127, 0, 0, 1], 0), async { let _ = shutdown_rx.await; })
+            .map_err(|e| anyhow!("Could not bind the report server: {}", e))?;
+
+        Ok(ReportServer {
+            uri: format!("http://{}", addr),
+            token,
+            state,
+            shutdown_tx,
+            handle: tokio::spawn(server)
+        })
+    }
+
+    /// Registers a task ID with the server, returning the channel its report arrives on.
+    pub fn register(&self, task_id: &str, task_name: &str) -> Receiver<Report> {
+        let (tx, rx) = channel::<Report>(10);
+        let route = TaskRoute { name: task_name.to_owned(), tx };
+        match self.state.routes.lock() {
+            Ok(mut a) => { a.insert(task_id.to_owned(), route); },
+            Err(e) => error!("Could not register task \"{}\" with the report server: {}", task_id, e)
+        }
+
+        rx
+    }
+
+    /// Stops routing reports for a task ID. Anything already received stays in its channel.
+    pub fn unregister(&self, task_id: &str) {
+        if let Ok(mut a) = self.state.routes.lock() {
+            a.remove(task_id);
+        }
+    }
+
+    /// Stops accepting connections and waits for in-flight requests to finish.
+    pub async fn shutdown(self) {
+        let _ = self.shutdown_tx.send(());
+        if let Err(e) = self.handle.await {
+            error!("Report server did not shut down cleanly: {}", e);
+        }
+    }
+}
+
+/// Waits for a task's report, giving a late report up to *grace* to arrive.
+///
+/// The task ID is unregistered afterwards, so any later posts for it are rejected.
+pub async fn receive_report(server: &ReportServer, task_id: &str, rx: &mut Receiver<Report>, grace: Duration) -> Option<Report> {
+    let report = match rx.try_recv() {
+        Ok(a) => Some(a),
+        Err(_) => tokio::time::timeout(grace, rx.recv()).await.ok().flatten()
+    };
+
+    server.unregister(task_id);
+    report
+}
+
+/// Builds a report for a task that never sent one, out of its captured output and how it exited.
+pub fn report_from_outcome(outcome: &TaskOutcome) -> Report {
+    let mut messages = vec![ReportMessage {
+        level: MessageLevel::Info,
+        text: format!("Task exited with {} without sending a report.", outcome.exit_description())
+    }];
+
+    if !outcome.stdout.trim().is_empty() {
+        messages.push(ReportMessage { level: MessageLevel::Info, text: format!("STDOUT:\n{}", outcome.stdout.trim_end()) });
+    }
+
+    if !outcome.stderr.trim().is_empty() {
+        messages.push(ReportMessage { level: MessageLevel::Warn, text: format!("STDERR:\n{}", outcome.stderr.trim_end()) });
+    }
+
+    Report {
+        version: REPORT_VERSION,
+        status: match outcome.success() {
+            true => ReportStatus::Success,
+            false => ReportStatus::Failure
+        },
+        messages,
+        changed: Vec::new(),
+        unchanged: Vec::new(),
+        outputs: Map::new(),
+        artifacts: Vec::new()
+    }
+}
+
+/// Logs a report, each message at its own level.
+pub fn log_report(task_name: &str, task_id: &str, report: &Report) {
+    let status_level = match report.status {
+        ReportStatus::Success => Level::Info,
+        ReportStatus::Warning => Level::Warn,
+        ReportStatus::Failure => Level::Error
+    };
+
+    log!(status_level, "Task \"{}\" of hash \"{}\" reported {}.", task_name, task_id, report.status);
+
+    for message in &report.messages {
+        log!(message.level.log_level(), "[{}] {}", task_name, message.text);
+    }
+
+    if !report.changed.is_empty() {
+        info!("[{}] Changed: {}", task_name, report.changed.join(", "));
+    }
+
+    if !report.unchanged.is_empty() {
+        info!("[{}] Unchanged: {}", task_name, report.unchanged.join(", "));
+    }
+
+    for (name, value) in &report.outputs {
+        info!("[{}] Output \"{}\": {}", task_name, name, value);
+    }
+
+    for artifact in &report.artifacts {
+        info!("[{}] Artifact: {}", task_name, artifact.display());
+    }
+}
+
+/// How a task of a run ended, as judged by Taskmaster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TaskStatus {
+    Succeeded,
+    Failed,
+    TimedOut,
+    Interrupted,
+    /// Never started, see the record's reason.
+    Skipped
+}
+
+impl TaskStatus {
+    /// Whether the task was run and did not succeed.
+    pub fn is_failure(&self) -> bool {
+        !matches!(self, TaskStatus::Succeeded | TaskStatus::Skipped)
+    }
+}
+
+impl fmt::Display for TaskStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TaskStatus::Succeeded => write!(f, "succeeded"),
+            TaskStatus::Failed => write!(f, "failed"),
+            TaskStatus::TimedOut => write!(f, "timed out"),
+            TaskStatus::Interrupted => write!(f, "interrupted"),
+            TaskStatus::Skipped => write!(f, "skipped")
+        }
+    }
+}
+
+/// Everything recorded about a single task of a run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskRecord {
+    /// Canonical name of the task, as found in the task definitions.
+    pub name: String,
+    /// "Id" the user gave the task, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Item variables of the task, if it is one of the copies made by "ForEach" or "Matrix".
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub items: Map<String, Value>,
+    /// Hash of the passthru data, which the task posted its report under.
+    pub task_id: String,
+    /// Passthru data the task's script was given.
+    pub parameters: Value,
+    pub status: TaskStatus,
+    /// Why the task was skipped, or why it failed before it could be started.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Whether a failure of the task was ignored, rather than failing the run.
+    pub continue_on_error: bool,
+    /// Which attempt at the task this is, counting from 1.
+    pub attempt: u32,
+    /// How the task's process exited, if it could be started at all.
+    pub outcome: Option<TaskOutcome>,
+    /// Report sent by the task, or built from its output when it was allowed not to send one.
+    pub report: Option<Report>,
+    /// Earlier attempts at the task that failed and were retried, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previous_attempts: Vec<AttemptRecord>
+}
+
+/// A failed attempt at a task that was retried.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttemptRecord {
+    pub attempt: u32,
+    pub task_id: String,
+    pub status: TaskStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub outcome: Option<TaskOutcome>,
+    pub report: Option<Report>
+}
+
+impl TaskRecord {
+    /// Starts a record for a task that has not run yet. It counts as failed until told otherwise.
+    pub fn new(name: &str, task_id: &str, parameters: Value) -> TaskRecord {
+        TaskRecord {
+            name: name.to_owned(),
+            id: None,
+            items: Map::new(),
+            task_id: task_id.to_owned(),
+            parameters,
+            status: TaskStatus::Failed,
+            reason: None,
+            continue_on_error: false,
+            attempt: 1,
+            outcome: None,
+            report: None,
+            previous_attempts: Vec::new()
+        }
+    }
+
+    /// Turns the record into that of an earlier attempt, once the task is retried.
+    pub fn into_attempt(self) -> AttemptRecord {
+        AttemptRecord {
+            attempt: self.attempt,
+            task_id: self.task_id,
+            status: self.status,
+            reason: self.reason,
+            outcome: self.outcome,
+            report: self.report
+        }
+    }
+
+    /// Records a task that was never started.
+    pub fn skipped(name: &str, reason: &str) -> TaskRecord {
+        let mut record = TaskRecord::new(name, "", Value::Null);
+        record.status = TaskStatus::Skipped;
+        record.reason = Some(reason.to_owned());
+        record
+    }
+}
+
+/// Counts the tasks of a run by how they ended, e.g. "Run summary: 2 succeeded, 1 failed, 0 skipped.".
+///
+/// Tasks that failed but were set to continue on error are counted on their own, as they don't fail the run.
+fn summary_line(records: &[TaskRecord]) -> String {
+    let succeeded = records.iter().filter(|a| a.status == TaskStatus::Succeeded).count();
+    let skipped = records.iter().filter(|a| a.status == TaskStatus::Skipped).count();
+    let continued = records.iter().filter(|a| a.status.is_failure() && a.continue_on_error).count();
+    let failed = records.len() - succeeded - skipped - continued;
+
+    match continued {
+        0 => format!("Run summary: {} succeeded, {} failed, {} skipped.", succeeded, failed, skipped),
+        a => format!("Run summary: {} succeeded, {} failed, {} continued on error, {} skipped.", succeeded, failed, a, skipped)
+    }
+}
+
+/// Lists the item variables of a task copied by "ForEach" or "Matrix", e.g. "item: web01" or "matrix.os: linux, matrix.arch: x64".
+fn items_label(items: &Map<String, Value>) -> String {
+    let pairs: Vec<String> = items.iter()
+        .map(|(name, value)| match value {
+            Value::String(a) => format!("{}: {}", name, a),
+            a => format!("{}: {}", name, a)
+        })
+        .collect();
+
+    pairs.join(", ")
+}
+
+/// Logs which tasks of a run succeeded, failed or were skipped, in the order they appear in the run.
+pub fn log_summary(records: &[TaskRecord]) {
+    info!("{}", summary_line(records));
+
+    for (position, record) in records.iter().enumerate() {
+        let level = match record.status {
+            TaskStatus::Succeeded => Level::Info,
+            TaskStatus::Skipped => Level::Warn,
+            _ if record.continue_on_error => Level::Warn,
+            _ => Level::Error
+        };
+
+        log!(level, "{}", summary_row(position, record));
+    }
+}
+
+/// Describes a task of a run in the summary, e.g. "  3. ExecBash (item: web01): failed after 2 attempts".
+fn summary_row(position: usize, record: &TaskRecord) -> String {
+    let mut line = format!("  {}. {}", position + 1, record.name);
+    if let Some(a) = &record.id { line.push_str(&format!(" [{}]", a)) }
+    if !record.items.is_empty() { line.push_str(&format!(" ({})", items_label(&record.items))) }
+    line.push_str(&format!(": {}", record.status));
+    if record.attempt > 1 { line.push_str(&format!(" after {} attempts", record.attempt)) }
+    if record.status.is_failure() && record.continue_on_error { line.push_str(", continued on error") }
+    if let Some(a) = &record.reason { line.push_str(&format!(" ({})", a)) }
+
+    line
+}
+
+/// The aggregated report of a whole run, as written by "run --report".
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunReport {
+    pub run_id: String,
+    pub started: DateTime<Local>,
+    pub finished: DateTime<Local>,
+    /// Name of the machine the run happened on.
+    pub host: String,
+    /// User the run happened as.
+    pub user: String,
+    /// Every task of the run, in the order they appear in the user's file.
+    pub tasks: Vec<TaskRecord>,
+    /// Undo scripts run when the run was rolled back, in the order they ran in.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rollback: Vec<TaskRecord>
+}
+
+/// Writes a run report to a file as pretty printed JSON, creating its folder if needed.
+pub async fn write_run_report(run_report: &RunReport, path: &Path) -> Result<()> {
+    let contents = serde_json::to_string_pretty(run_report)?;
+    if let Some(a) = path.parent() { ensure_dir(a.to_path_buf()).await? }
+    create_file(&contents, path.to_path_buf()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Server state with a single task "abc" registered under the token "secret".
+    fn state() -> (Arc<ServerState>, Receiver<Report>) {
+        let (tx, rx) = channel::<Report>(10);
+        let routes = HashMap::from([("abc".to_owned(), TaskRoute { name: "Task".to_owned(), tx })]);
+        let state = ServerState { routes: Mutex::new(routes), expected_auth: "Bearer secret".to_owned() };
+        (Arc::new(state), rx)
+    }
+
+    async fn post_report(task_id: &str, auth: Option<&str>, body: Value, state: &Arc<ServerState>) -> StatusCode {
+        let reply = handle_report(task_id.to_owned(), auth.map(str::to_owned), body, state.clone()).await.unwrap();
+        warp::Reply::into_response(reply).status()
+    }
+
+    #[test]
+    fn reports_are_checked_against_the_schema() {
+        let report = parse_report(serde_json::json!({"version": 1, "status": "warning", "outputs": {"count": 2}})).unwrap();
+        assert_eq!(report.status, ReportStatus::Warning);
+        assert_eq!(report.outputs["count"], 2);
+
+        let e = parse_report(serde_json::json!({"version": 2, "status": "success"})).unwrap_err();
+        assert!(e.to_string().contains("Unsupported report version 2"), "{}", e);
+
+        let e = parse_report(serde_json::json!({"version": 1, "status": "success", "changd": []})).unwrap_err();
+        assert!(e.to_string().contains("unknown field `changd`"), "{}", e);
+
+        let e = parse_report(serde_json::json!({"version": 1, "status": "done"})).unwrap_err();
+        assert!(e.to_string().contains("unknown variant `done`"), "{}", e);
+
+        assert!(parse_report(serde_json::json!({"version": 1, "status": "success", "messages": [{"text": "a", "color": "red"}]})).is_err());
+        assert!(parse_report(serde_json::json!({"status": "success"})).is_err());
+        assert!(parse_report(serde_json::json!(["success"])).is_err());
+    }
+
+    #[test]
+    fn posts_need_the_token_and_a_registered_task() {
+        let (state, _rx) = state();
+        let status = |a: Result<TaskRoute, Reply>| a.err().map(|b| warp::Reply::into_response(b).status());
+
+        assert!(authorize(&state, "abc", Some("Bearer secret".to_owned())).is_ok());
+        assert_eq!(status(authorize(&state, "abc", None)), Some(StatusCode::UNAUTHORIZED));
+        assert_eq!(status(authorize(&state, "abc", Some("Bearer wrong".to_owned()))), Some(StatusCode::UNAUTHORIZED));
+        assert_eq!(status(authorize(&state, "abc", Some("secret".to_owned()))), Some(StatusCode::UNAUTHORIZED));
+        assert_eq!(status(authorize(&state, "xyz", Some("Bearer secret".to_owned()))), Some(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn valid_reports_are_passed_on() {
+        let (state, mut rx) = state();
+        let body = serde_json::json!({"version": 1, "status": "success", "changed": ["a"]});
+
+        assert_eq!(post_report("abc", None, body.clone(), &state).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(post_report("abc", Some("Bearer wrong"), body.clone(), &state).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(post_report("xyz", Some("Bearer secret"), body.clone(), &state).await, StatusCode::NOT_FOUND);
+        assert_eq!(post_report("abc", Some("Bearer secret"), serde_json::json!({"version": 1, "status": "done"}), &state).await, StatusCode::BAD_REQUEST);
+        assert!(rx.try_recv().is_err());
+
+        assert_eq!(post_report("abc", Some("Bearer secret"), body, &state).await, StatusCode::OK);
+        assert_eq!(rx.try_recv().unwrap().changed, vec!["a".to_owned()]);
+    }
+
+    fn record(status: TaskStatus, continue_on_error: bool) -> TaskRecord {
+        let mut record = TaskRecord::new("Task", "", Value::Null);
+        record.status = status;
+        record.continue_on_error = continue_on_error;
+        record
+    }
+
+    #[test]
+    fn summary_counts_continued_failures_on_their_own() {
+        let records = vec![
+            record(TaskStatus::Succeeded, false),
+            record(TaskStatus::Failed, false),
+            record(TaskStatus::TimedOut, true),
+            record(TaskStatus::Failed, true),
+            TaskRecord::skipped("Task", "it was not needed")
+        ];
+        assert_eq!(summary_line(&records), "Run summary: 1 succeeded, 1 failed, 2 continued on error, 1 skipped.");
+        assert_eq!(summary_line(&records[..2]), "Run summary: 1 succeeded, 1 failed, 0 skipped.");
+    }
+
+    #[test]
+    fn summary_rows_tell_foreach_copies_apart() {
+        let mut first = record(TaskStatus::Failed, false);
+        first.items.insert("item".to_owned(), Value::from("web01"));
+        let mut second = record(TaskStatus::Succeeded, false);
+        second.id = Some("deploy".to_owned());
+        second.items.insert("matrix.os".to_owned(), Value::from("linux"));
+        second.items.insert("matrix.arch".to_owned(), Value::from(64));
+
+        assert_eq!(summary_row(0, &first), "  1. Task (item: web01): failed");
+        assert_eq!(summary_row(1, &second), "  2. Task [deploy] (matrix.arch: 64, matrix.os: linux): succeeded");
+        assert_eq!(summary_row(2, &record(TaskStatus::Succeeded, false)), "  3. Task: succeeded");
+    }
+
+    #[tokio::test]
+    async fn run_report_folders_are_created() {
+        let dir = std::env::temp_dir().join(format!("tm-report-{}", Uuid::new_v4()));
+        let path = dir.join("out").join("sub").join("r.json");
+        let now = Local::now();
+        let run_report = RunReport {
+            run_id: "run".to_owned(),
+            started: now,
+            finished: now,
+            host: "host".to_owned(),
+            user: "user".to_owned(),
+            tasks: vec![record(TaskStatus::Succeeded, false)],
+            rollback: Vec::new()
+        };
+
+        write_run_report(&run_report, &path).await.unwrap();
+        assert!(path.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,439 @@
+use regex::Regex;
+use serde_yml::{Mapping, Value};
+
+use std::fmt;
+
+use crate::modules::utils::sanitize_string;
+
+/// Type a task parameter can be declared with in "+Type".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParamType {
+    String,
+    Bool,
+    Int,
+    List,
+    Map,
+    /// Text naming a file or folder. It does not have to exist yet, as a task may be the one creating it.
+    Path
+}
+
+impl ParamType {
+    fn parse(name: &str) -> Option<ParamType> {
+        match name.trim().to_lowercase().as_str() {
+            "string" | "str" | "text" => Some(ParamType::String),
+            "bool" | "boolean" => Some(ParamType::Bool),
+            "int" | "integer" => Some(ParamType::Int),
+            "list" | "array" => Some(ParamType::List),
+            "map" | "mapping" => Some(ParamType::Map),
+            "path" => Some(ParamType::Path),
+            _ => None
+        }
+    }
+
+    /// Whether a value is of this type.
+    fn matches(&self, value: &Value) -> bool {
+        match self {
+            ParamType::String => value.is_string(),
+            ParamType::Bool => value.is_bool(),
+            ParamType::Int => value.is_i64() || value.is_u64(),
+            ParamType::List => value.is_sequence(),
+            ParamType::Map => value.is_mapping(),
+            ParamType::Path => value.as_str().is_some_and(|a| !a.trim().is_empty())
+        }
+    }
+}
+
+impl fmt::Display for ParamType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamType::String => write!(f, "text"),
+            ParamType::Bool => write!(f, "true or false"),
+            ParamType::Int => write!(f, "a whole number"),
+            ParamType::List => write!(f, "a list"),
+            ParamType::Map => write!(f, "a map"),
+            ParamType::Path => write!(f, "a path")
+        }
+    }
+}
+
+/// Describes what kind of value the user gave, for messages.
+pub fn describe(value: &Value) -> String {
+    match value {
+        Value::Null => "empty".to_owned(),
+        Value::Bool(a) => a.to_string(),
+        Value::Number(a) => format!("the number {}", a),
+        Value::String(a) if a.trim().is_empty() => "empty text".to_owned(),
+        Value::String(a) => format!("the text \"{}\"", a),
+        Value::Sequence(_) => "a list".to_owned(),
+        Value::Mapping(_) => "a map".to_owned(),
+        Value::Tagged(a) => describe(&a.value)
+    }
+}
+
+/// Writes a value on one line for messages as it would be written in YAML, e.g. `text`, `[1,2]` or `null`.
+pub fn inline(value: &Value) -> String {
+    match value {
+        Value::String(a) => a.to_owned(),
+        a => serde_json::to_string(a).unwrap_or_default()
+    }
+}
+
+/// Returns a "+" key of a parameter definition, such as "+Type", matched like other keys.
+pub fn meta<'a>(spec: &'a Mapping, name: &str) -> Option<&'a Value> {
+    spec.iter()
+        .find(|(a, _)| a.as_str().is_some_and(|b| b.starts_with('+') && sanitize_string(b) == name))
+        .map(|(_, a)| a)
+}
+
+/// Joins values for a message, e.g. `"a", "b" or "c"`.
+fn one_of(values: &[Value]) -> String {
+    let quoted: Vec<String> = values.iter()
+        .map(|a| match a {
+            Value::String(b) => format!("\"{}\"", b),
+            b => inline(b)
+        })
+        .collect();
+
+    match quoted.split_last() {
+        None => String::new(),
+        Some((a, [])) => a.to_owned(),
+        Some((a, b)) => format!("{} or {}", b.join(", "), a)
+    }
+}
+
+/// Whether a value is one of the values of an "+Enum". Text is compared without regard to case, as keys are.
+fn in_enum(value: &Value, values: &[Value]) -> bool {
+    values.iter().any(|a| match (a, value) {
+        (Value::String(b), Value::String(c)) => b.eq_ignore_ascii_case(c) || sanitize_string(b) == *c,
+        (b, c) => b == c
+    })
+}
+
+/// Returns a number of a "+" key of a parameter definition, such as "+Min", or adds a problem if it isn't one.
+fn meta_number(spec: &Mapping, name: &str, key: &str, path: &str, problems: &mut Vec<String>) -> Option<f64> {
+    match meta(spec, name)? {
+        Value::Number(a) => a.as_f64(),
+        a => {
+            problems.push(format!("parameter \"{}\" has \"{}: {}\" in its definition, which should be a number", path, key, inline(a)));
+            None
+        }
+    }
+}
+
+/// Number of items of a list or entries of a map.
+fn item_count(value: &Value) -> Option<usize> {
+    match value {
+        Value::Sequence(a) => Some(a.len()),
+        Value::Mapping(a) => Some(a.len()),
+        _ => None
+    }
+}
+
+/// Returns the parameters of a definition: its keys without a leading "+", whose values are their definitions.
+fn parameters(definition: &Mapping) -> impl Iterator<Item = (&str, &Mapping)> {
+    definition.iter().filter_map(|(a, b)| {
+        let name = a.as_str().filter(|c| !c.starts_with('+'))?;
+        Some((name, b.as_mapping()?))
+    })
+}
+
+/// Whether the runner can't know a value yet, as it references the outputs of another task.
+fn is_deferred(value: &Value) -> bool {
+    value.as_str().is_some_and(|a| a.contains("${tasks."))
+}
+
+/// Checks the user's data for a task against the "+Type", "+Required", "+Items", "+Keys" and value constraints of the
+/// parameters in its definition, as written in tasks.yaml. Returns every problem found.
+///
+/// *user_data* is expected to have sanitized keys, with aliases already resolved to the parameter names.
+pub fn check_parameters(user_data: &Value, definition: &Value) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Some(a) = definition.as_mapping() {
+        check_fields(user_data, a, "", &mut problems);
+    }
+
+    problems
+}
+
+/// Checks the fields of a map against the parameters of a definition.
+fn check_fields(data: &Value, definition: &Mapping, path: &str, problems: &mut Vec<String>) {
+    for (name, spec) in parameters(definition) {
+        let key = sanitize_string(name);
+        let value = data.as_mapping().and_then(|a| a.get(&key));
+        let path = match path.is_empty() {
+            true => name.to_owned(),
+            false => format!("{}/{}", path, name)
+        };
+
+        check_value(value, spec, &path, &format!("parameter \"{}\"", path), problems);
+    }
+}
+
+/// Checks a single value against its definition, adding what is wrong with it to *problems*.
+///
+/// *what* names the value in messages, e.g. `parameter "Drives"`.
+fn check_value(value: Option<&Value>, spec: &Mapping, path: &str, what: &str, problems: &mut Vec<String>) {
+    let required = match meta(spec, "required") {
+        None | Some(Value::Null) => false,
+        Some(Value::Bool(a)) => *a,
+        Some(a) => {
+            problems.push(format!("parameter \"{}\" has \"+Required: {}\" in its definition, which should be true or false", path, inline(a)));
+            false
+        }
+    };
+
+    let value = match value {
+        None | Some(Value::Null) => {
+            if required { problems.push(format!("{} is required", what)) }
+            return
+        },
+        Some(a) if is_deferred(a) => return,
+        Some(a) => a
+    };
+
+    if let Some(a) = meta(spec, "type") {
+        match a.as_str().and_then(ParamType::parse) {
+            Some(b) if !b.matches(value) => {
+                problems.push(format!("{} should be {}, not {}", what, b, describe(value)));
+                return
+            },
+            Some(_) => {},
+            None => problems.push(format!(
+                "parameter \"{}\" has \"+Type: {}\" in its definition, expected string, bool, int, list, map or path", path, inline(a)
+            ))
+        }
+    }
+
+    check_constraints(value, spec, path, what, problems);
+
+    // "+Keys" describes every key of a map, e.g. the drive letters of "Drives". Keys are checked the way the script gets
+    // them, sanitized, so "G:" is checked as "g" and a "+Pattern" only ever sees lowercase letters.
+    if let (Some(keys), Value::Mapping(map)) = (meta(spec, "keys"), value) {
+        match keys.as_mapping() {
+            Some(a) => for k in map.keys() {
+                check_value(Some(k), a, path, &format!("a name in parameter \"{}\", without case, numbers or symbols,", path), problems)
+            },
+            None => problems.push(format!("parameter \"{}\" has \"+Keys\" in its definition that is not a map of \"+Pattern\" and such", path))
+        }
+    }
+
+    // "+Items" describes every item of a list, or every value of a map.
+    if let Some(items) = meta(spec, "items") {
+        let items = match items.as_mapping() {
+            Some(a) => a,
+            None => {
+                problems.push(format!("parameter \"{}\" has \"+Items\" in its definition that is not a map of \"+Type\" and such", path));
+                return
+            }
+        };
+
+        match value {
+            Value::Sequence(a) => {
+                for (i, b) in a.iter().enumerate() {
+                    let item_path = format!("{}/{}", path, i);
+                    check_value(Some(b), items, &item_path, &format!("parameter \"{}\"", item_path), problems)
+                }
+            },
+            Value::Mapping(a) => {
+                for (k, b) in a {
+                    let key = k.as_str().map(|c| c.to_owned()).unwrap_or(inline(k));
+                    let item_path = format!("{}/{}", path, key);
+                    check_value(Some(b), items, &item_path, &format!("parameter \"{}\"", item_path), problems)
+                }
+            },
+            _ => {}
+        }
+    }
+
+    // A map may define parameters of its own.
+    if value.is_mapping() {
+        check_fields(value, spec, path, problems);
+    }
+}
+
+/// Checks a value against the "+Enum", "+Pattern", "+Min", "+Max", "+MinItems" and "+MaxItems" of its definition.
+fn check_constraints(value: &Value, spec: &Mapping, path: &str, what: &str, problems: &mut Vec<String>) {
+    if let Some(a) = meta(spec, "enum") {
+        match a.as_sequence() {
+            Some(b) if !in_enum(value, b) => problems.push(format!("{} should be {}, not {}", what, one_of(b), describe(value))),
+            Some(_) => {},
+            None => problems.push(format!("parameter \"{}\" has \"+Enum\" in its definition that is not a list of the allowed values", path))
+        }
+    }
+
+    if let Some(a) = meta(spec, "pattern") {
+        // The pattern has to match the whole value, so "[A-Z]" doesn't let "GG" through.
+        match a.as_str().map(|b| Regex::new(&format!("^(?:{})$", b))) {
+            Some(Ok(b)) => if value.as_str().is_some_and(|c| !b.is_match(c)) {
+                let expected = match meta(spec, "example").and_then(|d| d.as_str()) {
+                    Some(d) => format!("be written like \"{}\"", d),
+                    None => format!("match the pattern \"{}\"", a.as_str().unwrap_or_default())
+                };
+                problems.push(format!("{} should {}, not {}", what, expected, describe(value)))
+            },
+            Some(Err(_)) => problems.push(format!("parameter \"{}\" has \"+Pattern: {}\" in its definition, which is not a valid pattern", path, inline(a))),
+            None => problems.push(format!("parameter \"{}\" has \"+Pattern: {}\" in its definition, which should be text", path, inline(a)))
+        }
+    }
+
+    let number = value.as_f64();
+    if let (Some(a), Some(b)) = (meta_number(spec, "min", "+Min", path, problems), number) && b < a {
+        problems.push(format!("{} should be at least {}, not {}", what, a, b))
+    }
+    if let (Some(a), Some(b)) = (meta_number(spec, "max", "+Max", path, problems), number) && b > a {
+        problems.push(format!("{} should be at most {}, not {}", what, a, b))
+    }
+
+    let count = item_count(value);
+    if let (Some(a), Some(b)) = (meta_number(spec, "minitems", "+MinItems", path, problems), count) && (b as f64) < a {
+        problems.push(format!("{} should have at least {} item(s), but has {}", what, a, b))
+    }
+    if let (Some(a), Some(b)) = (meta_number(spec, "maxitems", "+MaxItems", path, problems), count) && (b as f64) > a {
+        problems.push(format!("{} should have at most {} item(s), but has {}", what, a, b))
+    }
+}
+
+/// Checks the "+" keys of the parameters of a definition, as written in tasks.yaml, and that their "+Data" defaults
+/// fit them. Returns every problem found, for "taskmaster lint".
+pub fn check_definition(definition: &Value) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Some(a) = definition.as_mapping() {
+        for (name, spec) in parameters(a) { check_spec(spec, name, &mut problems) }
+    }
+
+    problems
+}
+
+/// Checks the "+" keys of a parameter definition, then its default against them.
+fn check_spec(spec: &Mapping, path: &str, problems: &mut Vec<String>) {
+    let found = problems.len();
+
+    match meta(spec, "type") {
+        Some(a) if a.as_str().and_then(ParamType::parse).is_none() => problems.push(format!(
+            "parameter \"{}\" has \"+Type: {}\", expected string, bool, int, list, map or path", path, inline(a)
+        )),
+        _ => {}
+    }
+    if let Some(a) = meta(spec, "required").filter(|a| !a.is_bool() && !a.is_null()) {
+        problems.push(format!("parameter \"{}\" has \"+Required: {}\", which should be true or false", path, inline(a)));
+    }
+    if let Some(a) = meta(spec, "enum").filter(|a| !a.is_sequence()) {
+        problems.push(format!("parameter \"{}\" has \"+Enum: {}\", which should be a list of the allowed values", path, inline(a)));
+    }
+    match meta(spec, "pattern").map(|a| (a, a.as_str().map(|b| Regex::new(&format!("^(?:{})$", b))))) {
+        Some((a, None)) => problems.push(format!("parameter \"{}\" has \"+Pattern: {}\", which should be text", path, inline(a))),
+        Some((a, Some(Err(_)))) => problems.push(format!("parameter \"{}\" has \"+Pattern: {}\", which is not a valid pattern", path, inline(a))),
+        _ => {}
+    }
+    for (name, key) in [("min", "+Min"), ("max", "+Max"), ("minitems", "+MinItems"), ("maxitems", "+MaxItems")] {
+        if let Some(a) = meta(spec, name).filter(|a| !a.is_number()) {
+            problems.push(format!("parameter \"{}\" has \"{}: {}\", which should be a number", path, key, inline(a)));
+        }
+    }
+
+    for (name, key) in [("items", "+Items"), ("keys", "+Keys")] {
+        match meta(spec, name) {
+            Some(Value::Mapping(a)) => check_spec(a, &format!("{}/{}", path, key), problems),
+            Some(_) => problems.push(format!("parameter \"{}\" has \"{}\" that is not a map of \"+Type\" and such", path, key)),
+            None => {}
+        }
+    }
+    for (name, inner) in parameters(spec) { check_spec(inner, &format!("{}/{}", path, name), problems) }
+
+    // Only a default of a well formed definition is checked, so the same mistake isn't reported twice.
+    if problems.len() == found && let Some(a) = meta(spec, "data").filter(|a| !a.is_null()) {
+        check_value(Some(a), spec, path, &format!("the \"+Data\" default of parameter \"{}\"", path), problems);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::utils::sanitize_yaml;
+
+    /// Checks sanitized user data against a definition, both written in YAML.
+    fn problems(user_data: &str, definition: &str) -> Vec<String> {
+        let user_data: Value = serde_yml::from_str(user_data).unwrap();
+        let definition: Value = serde_yml::from_str(definition).unwrap();
+        check_parameters(&user_data, &definition)
+    }
+
+    #[test]
+    fn type_and_required() {
+        let definition = "Name: {+Type: string, +Required: true}\nCount: {+Type: int}\nOn: {+Type: bool}\nDir: {+Type: path}";
+
+        assert!(problems("{name: a, count: 2, on: true, dir: /tmp}", definition).is_empty());
+        assert_eq!(problems("{}", definition), vec!["parameter \"Name\" is required"]);
+        assert_eq!(problems("{name: a, count: two}", definition), vec!["parameter \"Count\" should be a whole number, not the text \"two\""]);
+        assert_eq!(problems("{name: a, on: yes please}", definition), vec!["parameter \"On\" should be true or false, not the text \"yes please\""]);
+        assert_eq!(problems("{name: a, dir: ' '}", definition), vec!["parameter \"Dir\" should be a path, not empty text"]);
+        assert_eq!(problems("{name: a, count: '${tasks.a.outputs.count}'}", definition), Vec::<String>::new());
+        assert_eq!(
+            problems("{name: a}", "Name: {+Type: word}"),
+            vec!["parameter \"Name\" has \"+Type: word\" in its definition, expected string, bool, int, list, map or path"]
+        );
+    }
+
+    #[test]
+    fn enum_ignores_case() {
+        let definition = "Mode: {+Enum: [Fast, Safe]}";
+
+        assert!(problems("{mode: safe}", definition).is_empty());
+        assert_eq!(problems("{mode: slow}", definition), vec!["parameter \"Mode\" should be \"Fast\" or \"Safe\", not the text \"slow\""]);
+    }
+
+    #[test]
+    fn pattern_matches_the_whole_value() {
+        let definition = "Letter: {+Pattern: '[A-Z]'}";
+
+        assert!(problems("{letter: G}", definition).is_empty());
+        assert_eq!(problems("{letter: GG}", definition), vec!["parameter \"Letter\" should match the pattern \"[A-Z]\", not the text \"GG\""]);
+        assert_eq!(
+            problems("{letter: GG}", "Letter: {+Pattern: 'A|B', +Example: A}"),
+            vec!["parameter \"Letter\" should be written like \"A\", not the text \"GG\""]
+        );
+        assert_eq!(problems("{letter: AB}", "Letter: {+Pattern: 'A|B'}").len(), 1);
+    }
+
+    #[test]
+    fn min_max_and_item_counts() {
+        let definition = "Port: {+Min: 1, +Max: 65535}\nHosts: {+MinItems: 1, +MaxItems: 2}";
+
+        assert!(problems("{port: 80, hosts: [a]}", definition).is_empty());
+        assert_eq!(problems("{port: 0}", definition), vec!["parameter \"Port\" should be at least 1, not 0"]);
+        assert_eq!(problems("{port: 70000}", definition), vec!["parameter \"Port\" should be at most 65535, not 70000"]);
+        assert_eq!(problems("{hosts: []}", definition), vec!["parameter \"Hosts\" should have at least 1 item(s), but has 0"]);
+        assert_eq!(problems("{hosts: {a: 1, b: 2, c: 3}}", definition), vec!["parameter \"Hosts\" should have at most 2 item(s), but has 3"]);
+        assert_eq!(
+            problems("{port: 80}", "Port: {+Min: one}"),
+            vec!["parameter \"Port\" has \"+Min: one\" in its definition, which should be a number"]
+        );
+    }
+
+    #[test]
+    fn keys_are_checked_sanitized() {
+        let definition = "Drives: {+Type: map, +Keys: {+Pattern: '[a-z]'}}";
+
+        // "G:" reaches the check as "g", the way the script gets it
+        let sanitized = sanitize_yaml(&serde_yml::from_str("{drives: {'G:': x}}").unwrap()).unwrap();
+        let definition_value: Value = serde_yml::from_str(definition).unwrap();
+        assert!(check_parameters(&sanitized, &definition_value).is_empty());
+
+        assert_eq!(
+            problems("{drives: {gh: x}}", definition),
+            vec!["a name in parameter \"Drives\", without case, numbers or symbols, should match the pattern \"[a-z]\", not the text \"gh\""]
+        );
+    }
+
+    #[test]
+    fn definitions_are_linted() {
+        let definition: Value = serde_yml::from_str("Name: {+Type: word, +Required: maybe, +Enum: a}\nPort: {+Type: int, +Data: none}").unwrap();
+
+        assert_eq!(check_definition(&definition), vec![
+            "parameter \"Name\" has \"+Type: word\", expected string, bool, int, list, map or path",
+            "parameter \"Name\" has \"+Required: maybe\", which should be true or false",
+            "parameter \"Name\" has \"+Enum: a\", which should be a list of the allowed values",
+            "the \"+Data\" default of parameter \"Port\" should be a whole number, not the text \"none\""
+        ]);
+    }
+}
@@ -0,0 +1,432 @@
+use anyhow::{Result, anyhow};
+
+use tokio::process::Command;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::watch::Receiver;
+use tokio::time::{self, sleep};
+
+use std::{
+    env,
+    process::Stdio,
+    path::{Path, PathBuf},
+    time::{Duration, Instant}
+};
+
+use log::{error, warn};
+
+use serde::{Deserialize, Serialize};
+
+use crate::modules::utils::{create_file, delete_file, ensure_dir, serialize_secs, deserialize_secs};
+
+/// Environment variable holding the folder of a task's script, so the script can find the files it comes with.
+pub const TASK_DIR_VAR: &str = "TM_TASK_DIR";
+
+/// How long output is still read after a process ends, in case something it started holds on to its pipes.
+const PIPE_GRACE: Duration = Duration::from_secs(5);
+
+/// Describes how a task script is written, how its data preamble is rendered and how its process is started.
+///
+/// Each supported shell implements this, and a backend is picked per task by the extension of its script.
+pub trait ShellBackend: Send + Sync {
+    /// Human readable name of the shell, used in logging.
+    fn name(&self) -> &'static str;
+
+    /// Extension of scripts run by this shell, without the leading dot.
+    fn extension(&self) -> &'static str;
+
+    /// Renders the preamble that exposes the task data, task ID, report URI and report token to the script.
+    fn render_preamble(&self, task_data: &str, task_id: &str, report_uri: &str, report_token: &str) -> String;
+
+    /// Joins the preamble and the task source code into the final script.
+    fn render_script(&self, preamble: &str, code: &str) -> String {
+        format!("{}{}", preamble, code)
+    }
+
+    /// Location the final script is written to before it is run.
+    ///
+    /// The "tm-run-" prefix keeps it apart from the files tasks write to ".tm_temp" themselves, which are named after
+    /// the task ID, e.g. the inner script of ExecPowerShell.
+    fn script_path(&self, temp_dir: &Path, task_id: &str) -> PathBuf {
+        temp_dir.join(format!("tm-run-{}.{}", task_id, self.extension()))
+    }
+
+    /// Builds the command that runs the script at the given path.
+    fn command(&self, script_path: &Path, elevated: bool) -> Command;
+}
+
+/// How a task's process came to an end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Termination {
+    /// The process exited on its own.
+    Exited,
+    /// The process tree was killed after running past its timeout.
+    TimedOut,
+    /// The process tree was killed because the run was interrupted (Ctrl-C).
+    Interrupted
+}
+
+/// What happened when a task's script was run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskOutcome {
+    pub termination: Termination,
+    /// Exit code of the process, None if it was ended by a signal or killed.
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// Wall-clock time between spawning the process and it exiting.
+    #[serde(rename = "durationSecs", serialize_with = "serialize_secs", deserialize_with = "deserialize_secs")]
+    pub duration: Duration
+}
+
+impl TaskOutcome {
+    /// Whether the process exited on its own with a code of 0.
+    pub fn success(&self) -> bool {
+        self.termination == Termination::Exited && self.exit_code == Some(0)
+    }
+
+    /// Describes how the process exited, for logging.
+    pub fn exit_description(&self) -> String {
+        match (self.termination, self.exit_code) {
+            (Termination::TimedOut, _) => "a timeout".to_owned(),
+            (Termination::Interrupted, _) => "an interrupt".to_owned(),
+            (Termination::Exited, Some(a)) => format!("exit code {}", a),
+            (Termination::Exited, None) => "no exit code (terminated by a signal)".to_owned()
+        }
+    }
+}
+
+/// Windows PowerShell (`powershell.exe`).
+pub struct WindowsPowerShell;
+
+/// PowerShell 7+ (`pwsh`).
+pub struct Pwsh;
+
+/// GNU Bash.
+pub struct Bash;
+
+/// Any POSIX compliant `sh`.
+pub struct Sh;
+
+/// Quotes a string slice as a PowerShell single quoted literal.
+fn quote_powershell(input: &str) -> String {
+    format!("'{}'", input.replace('\'', "''"))
+}
+
+/// Quotes a string slice as a POSIX shell single quoted literal.
+fn quote_posix(input: &str) -> String {
+    format!("'{}'", input.replace('\'', "'\\''"))
+}
+
+/// Renders the `$taskData`/`$taskId`/`$reportUri`/`$reportToken` preamble shared by both PowerShell flavours.
+fn powershell_preamble(task_data: &str, task_id: &str, report_uri: &str, report_token: &str) -> String {
+    format!(
+        "$taskData = {}\r\n$taskId = {}\r\n$reportUri = {}\r\n$reportToken = {}\r\n",
+        quote_powershell(task_data),
+        quote_powershell(task_id),
+        quote_powershell(report_uri),
+        quote_powershell(report_token)
+    )
+}
+
+/// Renders the `taskData`/`taskId`/`reportUri`/`reportToken` preamble shared by the POSIX style shells.
+fn posix_preamble(task_data: &str, task_id: &str, report_uri: &str, report_token: &str) -> String {
+    format!(
+        "taskData={}\ntaskId={}\nreportUri={}\nreportToken={}\n",
+        quote_posix(task_data),
+        quote_posix(task_id),
+        quote_posix(report_uri),
+        quote_posix(report_token)
+    )
+}
+
+/// Builds a command that has a PowerShell executable run the script.
+///
+/// Elevation needs *Start-Process -Verb RunAs*, which runs the script in a new window.
+/// Its exit code is passed back, but its output can not be captured.
+fn powershell_command(executable: &str, script_path: &Path, elevated: bool) -> Command {
+    let mut cmd = Command::new(executable);
+    cmd.args(["-NoProfile", "-ExecutionPolicy", "Bypass"]);
+
+    if !elevated {
+        cmd.arg("-File");
+        cmd.arg(script_path);
+        return cmd
+    }
+
+    let inner_proc_cmd = format!("'-NoProfile -ExecutionPolicy Bypass -File \"{}\"'", script_path.display());
+
+    cmd.arg("-Command");
+    cmd.arg(format!(
+        "$proc = Start-Process {} -ArgumentList {} -Verb RunAs -Wait -PassThru; exit $proc.ExitCode",
+        executable,
+        inner_proc_cmd
+    ));
+    cmd
+}
+
+/// Builds a command that has a POSIX style shell run the script, through sudo if elevated.
+///
+/// Nothing can answer a password prompt, so sudo is run non-interactively and fails if it needs one. It keeps
+/// *TASK_DIR_VAR*, which it would otherwise leave out of the script's environment.
+fn posix_command(executable: &str, script_path: &Path, elevated: bool) -> Command {
+    let mut cmd = match elevated {
+        true => {
+            let mut a = Command::new("sudo");
+            a.args(["-n", &format!("--preserve-env={}", TASK_DIR_VAR), executable]);
+            a
+        },
+        false => Command::new(executable)
+    };
+
+    cmd.arg(script_path);
+    cmd
+}
+
+impl ShellBackend for WindowsPowerShell {
+    fn name(&self) -> &'static str { "Windows PowerShell" }
+
+    fn extension(&self) -> &'static str { "ps1" }
+
+    fn render_preamble(&self, task_data: &str, task_id: &str, report_uri: &str, report_token: &str) -> String {
+        powershell_preamble(task_data, task_id, report_uri, report_token)
+    }
+
+    fn command(&self, script_path: &Path, elevated: bool) -> Command {
+        powershell_command("powershell.exe", script_path, elevated)
+    }
+}
+
+impl ShellBackend for Pwsh {
+    fn name(&self) -> &'static str { "PowerShell" }
+
+    fn extension(&self) -> &'static str { "pwsh.ps1" }
+
+    fn render_preamble(&self, task_data: &str, task_id: &str, report_uri: &str, report_token: &str) -> String {
+        powershell_preamble(task_data, task_id, report_uri, report_token)
+    }
+
+    fn command(&self, script_path: &Path, elevated: bool) -> Command {
+        powershell_command("pwsh", script_path, elevated)
+    }
+}
+
+impl ShellBackend for Bash {
+    fn name(&self) -> &'static str { "Bash" }
+
+    fn extension(&self) -> &'static str { "sh" }
+
+    fn render_preamble(&self, task_data: &str, task_id: &str, report_uri: &str, report_token: &str) -> String {
+        posix_preamble(task_data, task_id, report_uri, report_token)
+    }
+
+    fn command(&self, script_path: &Path, elevated: bool) -> Command {
+        posix_command("bash", script_path, elevated)
+    }
+}
+
+impl ShellBackend for Sh {
+    fn name(&self) -> &'static str { "sh" }
+
+    fn extension(&self) -> &'static str { "posix.sh" }
+
+    fn render_preamble(&self, task_data: &str, task_id: &str, report_uri: &str, report_token: &str) -> String {
+        posix_preamble(task_data, task_id, report_uri, report_token)
+    }
+
+    fn command(&self, script_path: &Path, elevated: bool) -> Command {
+        posix_command("sh", script_path, elevated)
+    }
+}
+
+/// Script extensions Taskmaster looks for, in order of preference for the current platform.
+///
+/// Compound extensions come before the plain extension they end with so they are matched first.
+pub fn script_extensions() -> Vec<&'static str> {
+    match cfg!(windows) {
+        true => vec!["pwsh.ps1", "ps1", "posix.sh", "sh"],
+        false => vec!["posix.sh", "sh", "pwsh.ps1", "ps1"]
+    }
+}
+
+/// Picks the shell backend for a script based on its file name.
+///
+/// Plain `.ps1` scripts run under Windows PowerShell on Windows, and under `pwsh` everywhere else.
+pub fn backend_for_script(script_path: &Path) -> Option<Box<dyn ShellBackend>> {
+    let file_name = script_path.file_name()?.to_str()?.to_ascii_lowercase();
+
+    if file_name.ends_with(".pwsh.ps1") { return Some(Box::new(Pwsh)) }
+    if file_name.ends_with(".posix.sh") { return Some(Box::new(Sh)) }
+    if file_name.ends_with(".sh") { return Some(Box::new(Bash)) }
+    if file_name.ends_with(".ps1") {
+        return match cfg!(windows) {
+            true => Some(Box::new(WindowsPowerShell)),
+            false => Some(Box::new(Pwsh))
+        }
+    }
+
+    None
+}
+
+/// Kills a process and every process it started.
+///
+/// On Unix the task is the leader of its own process group, so the whole group is signalled.
+/// On Windows *taskkill* walks the process tree.
+pub async fn kill_process_tree(pid: u32) -> Result<()> {
+    let mut cmd = match cfg!(windows) {
+        true => {
+            let mut a = Command::new("taskkill");
+            a.args(["/F", "/T", "/PID", &pid.to_string()]);
+            a
+        },
+        false => {
+            let mut a = Command::new("kill");
+            a.args(["-KILL", "--", &format!("-{}", pid)]);
+            a
+        }
+    };
+
+    cmd.stdout(Stdio::null());
+    cmd.stderr(Stdio::null());
+
+    match cmd.status().await?.success() {
+        true => Ok(()),
+        false => Err(anyhow!("Could not kill the process tree of PID {}.", pid))
+    }
+}
+
+/// Reads a child process pipe to a String until it closes.
+async fn read_pipe<R: AsyncRead + Unpin>(pipe: Option<R>) -> String {
+    let mut buffer = Vec::new();
+    if let Some(mut a) = pipe {
+        let _ = a.read_to_end(&mut buffer).await;
+    }
+
+    String::from_utf8_lossy(&buffer).into_owned()
+}
+
+/// Deletes the temporary file of a script that has run. A file that can't be deleted is only worth a warning, as the
+/// task has already run.
+async fn remove_script(script_path: PathBuf) {
+    if let Err(e) = delete_file(script_path.clone()).await {
+        warn!("Could not delete the temporary script \"{}\": {}", script_path.display(), e);
+    }
+}
+
+/// Writes a script to a temporary file and runs it with the given shell backend, capturing its outcome.
+///
+/// The process tree is killed if it runs past the timeout, or once *interrupt* becomes true.
+/// The temporary script is deleted once the process exits.
+///
+/// The script runs from the working directory, and is told the folder its task's files are in through *TASK_DIR_VAR*.
+pub async fn spawn_script(
+    backend: &dyn ShellBackend,
+    script: &str,
+    task_dir: &Path,
+    task_id: &str,
+    elevated: bool,
+    timeout: Option<Duration>,
+    mut interrupt: Receiver<bool>
+) -> Result<TaskOutcome> {
+    let script_dir = env::current_dir()?.join(".tm_temp");
+    ensure_dir(script_dir.clone()).await?;
+
+    let script_path = backend.script_path(&script_dir, task_id);
+    if script_path.to_str().is_none() {
+        return Err(anyhow!("Could not convert temp script path to string. Script Content: \n\"{}\"", script))
+    }
+
+    create_file(script, script_path.clone()).await?;
+
+    let mut cmd = backend.command(&script_path, elevated);
+    cmd.env(TASK_DIR_VAR, task_dir);
+
+    cmd.stdin(Stdio::null());
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    cmd.kill_on_drop(true);
+
+    #[cfg(unix)]
+    cmd.process_group(0);
+
+    let start = Instant::now();
+    let mut child = match cmd.spawn() {
+        Ok(a) => a,
+        Err(e) => {
+            remove_script(script_path).await;
+            return Err(anyhow!(e))
+        }
+    };
+
+    let stdout = tokio::spawn(read_pipe(child.stdout.take()));
+    let stderr = tokio::spawn(read_pipe(child.stderr.take()));
+
+    let timer = async {
+        match timeout {
+            Some(a) => sleep(a).await,
+            None => std::future::pending().await
+        }
+    };
+
+    let (termination, status) = tokio::select! {
+        status = child.wait() => (Termination::Exited, status),
+        _ = timer => (Termination::TimedOut, Err(std::io::ErrorKind::TimedOut.into())),
+        // Only a real interrupt, not the sender going away, ends the task.
+        Ok(_) = interrupt.wait_for(|a| *a) => (Termination::Interrupted, Err(std::io::ErrorKind::Interrupted.into()))
+    };
+
+    if termination != Termination::Exited {
+        if let Some(pid) = child.id() && let Err(e) = kill_process_tree(pid).await {
+            error!("{}", e);
+        }
+
+        let _ = child.kill().await;
+    }
+
+    let duration = start.elapsed();
+
+    remove_script(script_path).await;
+
+    let exit_code = match termination {
+        Termination::Exited => status?.code(),
+        _ => None
+    };
+
+    Ok(TaskOutcome {
+        termination,
+        exit_code,
+        stdout: time::timeout(PIPE_GRACE, stdout).await.ok().and_then(|a| a.ok()).unwrap_or_default(),
+        stderr: time::timeout(PIPE_GRACE, stderr).await.ok().and_then(|a| a.ok()).unwrap_or_default(),
+        duration
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn dropped_interrupt_sender_does_not_interrupt() {
+        let (sender, interrupt) = tokio::sync::watch::channel(false);
+        drop(sender);
+
+        let outcome = spawn_script(&Sh, "sleep 0.2\nexit 3\n", Path::new("."), "test-dropped-interrupt", false, None, interrupt).await.unwrap();
+        assert!(outcome.termination == Termination::Exited);
+        assert_eq!(outcome.exit_code, Some(3));
+    }
+
+    #[tokio::test]
+    async fn interrupt_kills_the_script() {
+        let (sender, interrupt) = tokio::sync::watch::channel(false);
+        let task = tokio::spawn(async move {
+            spawn_script(&Sh, "sleep 30\n", Path::new("."), "test-interrupt", false, None, interrupt).await
+        });
+
+        sleep(Duration::from_millis(200)).await;
+        sender.send(true).unwrap();
+
+        let outcome = time::timeout(Duration::from_secs(10), task).await.unwrap().unwrap().unwrap();
+        assert!(outcome.termination == Termination::Interrupted);
+    }
+}
@@ -0,0 +1,89 @@
+use anyhow::{Result, anyhow};
+
+use serde::{Deserialize, Serialize};
+use serde_yml::Value;
+
+use sha256::digest;
+
+use chrono::{DateTime, Local};
+
+use tokio::fs::rename;
+
+use std::path::PathBuf;
+
+use crate::modules::report::TaskRecord;
+use crate::modules::utils::{create_file, ensure_dir, file_contents};
+use crate::modules::vars::Variables;
+
+/// Folder the state of runs is kept in, relative to the working directory like ".tm_temp".
+const STATE_DIR: &str = ".tm_state";
+
+/// What a run has done so far, saved after every task so an interrupted run can be picked up with "run --resume".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunState {
+    pub run_id: String,
+    pub started: DateTime<Local>,
+    /// Hash of what the run was started with, see *run_input_hash()*.
+    pub input_hash: String,
+    /// Records of the tasks that have finished or were skipped so far, by position in the run.
+    pub tasks: Vec<Option<TaskRecord>>
+}
+
+impl RunState {
+    /// Reads the state of an earlier run.
+    pub async fn load(run_id: &str) -> Result<RunState> {
+        let path = state_path(run_id)?;
+        if !path.exists() {
+            return Err(anyhow!("There is no saved state for run \"{}\" at \"{}\".", run_id, path.display()))
+        }
+
+        let contents = file_contents(&path).await?;
+        serde_json::from_str(&contents)
+            .map_err(|e| anyhow!("The saved state of run \"{}\" at \"{}\" could not be read: {}", run_id, path.display(), e))
+    }
+
+    /// Writes the state of the run, replacing what was saved before only once the new state is fully written.
+    pub async fn save(&self) -> Result<()> {
+        let path = state_path(&self.run_id)?;
+        let partial = path.with_extension("json.partial");
+
+        ensure_dir(PathBuf::from(STATE_DIR)).await?;
+        create_file(&serde_json::to_string_pretty(self)?, partial.clone()).await?;
+        rename(&partial, &path).await?;
+
+        Ok(())
+    }
+}
+
+/// Where the state of a run is saved.
+fn state_path(run_id: &str) -> Result<PathBuf> {
+    // Run IDs end up in a path, so only allow what *new_run_id()* gives out.
+    if run_id.is_empty() || !run_id.chars().all(|a| a.is_ascii_alphanumeric() || a == '-') {
+        return Err(anyhow!("\"{}\" is not a valid run ID, run IDs look like \"20240612-153045-3f9a1c\".", run_id))
+    }
+
+    Ok(PathBuf::from(STATE_DIR).join(format!("{}.json", run_id)))
+}
+
+/// Hashes what a run is started with: the user's file, the variables once resolved from every source, and the task
+/// definitions. Comments and formatting are not part of it, so only changes to what they say count.
+pub fn run_input_hash(user_input: &Value, variables: &Variables, task_input: &Value) -> Result<String> {
+    let input = Value::Sequence(vec![user_input.to_owned(), variables.to_value(), task_input.to_owned()]);
+    Ok(digest(serde_yml::to_string(&input)?).to_ascii_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_ids_stay_inside_the_state_folder() {
+        assert_eq!(state_path("20240612-153045-3f9a1c").unwrap(), PathBuf::from(".tm_state/20240612-153045-3f9a1c.json"));
+
+        for run_id in ["", "../secrets", "a/b", "a\\b", "run.old", "run id", "~"] {
+            let e = state_path(run_id).unwrap_err();
+            assert!(e.to_string().contains("is not a valid run ID"), "{}", e);
+        }
+    }
+}
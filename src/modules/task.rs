@@ -1,82 +1,86 @@
 use anyhow::{anyhow, Result};
 
 use crate::modules::utils::{
-    create_file, 
     sanitize_yaml, 
     sanitize_value, 
     sanitize_string, 
-    file_contents,
+    duration_from_value,
     handle_logged_result
 };
 
 use serde_json;
 use serde_yml::{Sequence, Value, Mapping};
 
-use tokio::sync::mpsc::Sender;
-use tokio::process::Command;
-use tokio::sync::Mutex;
+use tokio::sync::watch;
 use tokio::fs::read_to_string;
+use tokio::signal::ctrl_c;
+use tokio::task::JoinHandle;
 
-use warp::Filter;
-use warp::http::StatusCode;
+use futures::stream::{FuturesUnordered, StreamExt};
 
 use sha256::digest;
 
+use uuid::Uuid;
+
+use chrono::Local;
+
 use std::{
-    env, 
-    process::Stdio, 
+    collections::HashMap,
     str,
-    sync::Arc,
-    path::{Path, PathBuf}
+    path::PathBuf,
+    time::{Duration, Instant}
 };
 
-use log::{error, info};
-
-use super::utils::delete_file;
-
-
-/// Spawns a powershell process with a given script block in string slice form.
-pub async fn spawn_powershell(script: &str, elevated: bool) -> Result<String> {
-    let mut cmd = Command::new("powershell.exe");
-
-    cmd.stdin(Stdio::piped());
-    cmd.stdout(Stdio::piped());
-    cmd.stderr(Stdio::piped());
-
-    let script_dir = env::current_dir()?.join(".tm_temp\\");
-
-    let script_path_raw = script_dir.join("temp.ps1");
-    let script_path = script_path_raw.to_str().ok_or(anyhow!("Could not convert temp script path to string. Script Content: \n\"{}\"", script))?;
-
-    create_file(script, PathBuf::from(script_path)).await?;
-
-    let mut inner_proc_cmd = format!("'-NoProfile -ExecutionPolicy Bypass -File \"{}\"'", script_path);
-    
-    if elevated { 
-        inner_proc_cmd += " -Verb RunAs";
-    };
-
-    let start_process_cmd = format!("Start-Process powershell.exe -ArgumentList {} -Wait", inner_proc_cmd);
-
-    cmd.arg("-Command");
-    cmd.arg(&start_process_cmd);
-    let output = cmd.spawn()?.wait_with_output().await?;
+use log::{error, info, warn};
+
+use crate::modules::report::{
+    ReportServer,
+    ReportStatus,
+    RunReport,
+    AttemptRecord,
+    TaskRecord,
+    TaskStatus,
+    REPORT_GRACE,
+    log_report,
+    log_summary,
+    receive_report,
+    report_from_outcome,
+    write_run_report
+};
+use crate::modules::expr::ExprValue;
+use crate::modules::vars::{Variables, interpolate};
+use crate::modules::state::{RunState, run_input_hash};
+use crate::modules::schema::check_parameters;
+use crate::modules::graph::{TaskNode, build_task_graph, task_label};
+use crate::modules::registry::{load_task_definitions, tasks_dir};
+use crate::modules::shell::{ShellBackend, TaskOutcome, Termination, backend_for_script, script_extensions, spawn_script};
 
-    delete_file(PathBuf::from(script_path)).await?;
 
-    Ok(String::from_utf8(output.stdout)?)
+/// Gets the location of a script of task *task*, in the tasks folder or in the task's own folder, "tasks/<task>".
+///
+/// If no extension is given, the first script found out of the extensions in *script_extensions()* is returned,
+/// looking in the tasks folder before the task's folder, and falling back to the most preferred extension in the tasks
+/// folder if none exist.
+pub fn get_task_script(name: &str, task: &str, ext: Option<&str>) -> Result<PathBuf> {
+    let tasks_dir = tasks_dir()?;
+
+    if let Some(a) = ext {
+        return Ok(tasks_dir.join(format!("{}.{}", name, a)))
+    }
 
-}
+    let task_dir = tasks_dir.join(task);
+    let dirs = [tasks_dir, task_dir];
 
-/// Gets the location of the tasks folder relative to the current exe. Returns ps1 path from there.
-pub fn get_task_script(name: &str, ext: Option<&str>) -> Result<PathBuf> {
-    let ext_str = ext.unwrap_or("ps1");
-    let rel_script_path = PathBuf::from(&format!("tasks\\{}.{}", name, ext_str));
+    let candidates: Vec<PathBuf> = script_extensions()
+        .iter()
+        .flat_map(|a| dirs.iter().map(move |b| b.join(format!("{}.{}", name, a))))
+        .collect();
 
-    let exe_path = env::current_dir()?;
-    let script_path = exe_path.join(rel_script_path);
+    for candidate in &candidates {
+        if candidate.exists() { return Ok(candidate.to_owned()) }
+    }
 
-    Ok(script_path)
+    candidates.into_iter().next().ok_or(anyhow!("No script extensions are supported on this platform."))
 }
 
 /// Retreives user data from a serde data structure.
@@ -96,7 +100,7 @@ pub fn user_input_by_path(user_data: &Value, task_data: &Value, path: &str) -> O
         let task_map = task_data.as_mapping()?;
 
         // If task somehow cannot find the path part, we screwed up the path or gave a task bad data
-        let task_inner = match task_map.get(&next_part) { Some(a) => a, None => return None };
+        let task_inner = task_map.get(&next_part)?;
 
         // Retrive aliases from task data
         let mut aliases = Sequence::new();
@@ -114,7 +118,10 @@ pub fn user_input_by_path(user_data: &Value, task_data: &Value, path: &str) -> O
     Some((user_data.clone(), path_remains))
 }
 
-/// Retreive data from a task via a string path.
+/// Retreive data from a task via a string path, returning the entry and the key it was found under.
+///
+/// Each part of the path matches a key, or one of the "+Aliases" of its entry, once both are sanitized. This works on
+/// the raw task definitions as well as sanitized ones, where "+Aliases" has become "aliases".
 ///
 /// # Example
 /// "MapDrive/Elevated" would evaluate to True/False
@@ -135,43 +142,33 @@ pub fn get_by_path(data: &Value, path: &str, parent: Option<&Value>) -> Option<(
         return get_by_path(a, &path_remains, Some(&b))
     }
 
-    let mut aliases: Vec<String> = Vec::new();
-
-    for key in data.as_mapping()?.keys() {
-        let entry = data.get(key)?;
-        if let Some(a) = entry.get("+Aliases") {
-            let mut alias_seq = Vec::new();
-            for b in a.as_sequence()? {
-                alias_seq.push(
-                    match b.as_str() { Some(c) => c.to_owned(), None => continue }   
-                )
-            }
-
-            if !alias_seq.contains(&raw_next_part.to_owned()) {
-                continue
-            }
-
-            aliases.extend(alias_seq);
-                break
-            }
-        }
-
-
-    for a in &aliases {
-        match data.get(a) {
-            Some(b) => {
-                let c = Value::String(a.to_owned());
-                return get_by_path(b, &path_remains, Some(&c))
-            },
-            None => continue
-        }
-    }
-
-    None
+    // Otherwise find the entry whose key matches once sanitized, so raw and sanitized data both work. Only then are
+    // aliases looked at, so the alias of one entry can't hide another entry with that very name.
+    let map = data.as_mapping()?;
+    let by_key = map.iter().find(|(key, _)| key.as_str().is_some_and(|a| sanitize_string(a) == next_part));
+    let by_alias = || map.iter().find(|(_, entry)| {
+        entry.get("+Aliases").or(entry.get("aliases"))
+            .and_then(|a| a.as_sequence())
+            .is_some_and(|a| a.iter().filter_map(sanitize_value).any(|b| b.as_str() == Some(next_part.as_str())))
+    });
+
+    let (key, entry) = by_key.or_else(by_alias)?;
+    get_by_path(entry, &path_remains, Some(key))
 }
 
-/// Returns a tuple of the task source code with injected data, and a hash which is the task ID.
-pub fn prep_passthru_args(user_data: &Value, task_data: &Value) -> Option<(String, String)> {
+/// Returns a tuple of the script preamble with injected data for the given shell, a hash which is the task ID,
+/// and the injected data itself.
+///
+/// The task ID is hashed from the salt followed by the injected data, so the same data can be given distinct IDs.
+/// The preamble also carries the URI and token the script posts its report with.
+pub fn prep_passthru_args(
+    user_data: &Value,
+    task_data: &Value,
+    backend: &dyn ShellBackend,
+    report_uri: &str,
+    report_token: &str,
+    salt: &str
+) -> Option<(String, String, serde_json::Value)> {
     // let task_name = get_task_name(task_data)?;
     let passthru_path = "Passthru/";
 
@@ -179,10 +176,18 @@ pub fn prep_passthru_args(user_data: &Value, task_data: &Value) -> Option<(Strin
 
     let output_ps: Option<String>;
     let task_id: String;
-    match serde_json::to_string(&passthru_data) {
+    let parameters = match serde_json::to_value(&passthru_data) {
+        Ok(a) => a,
+        Err(e) => {
+            error!("Error occured: {}", anyhow!(e));
+            return None;
+        }
+    };
+
+    match serde_json::to_string(&parameters) {
         Ok(a) => { 
             output_ps = Some(a.clone());
-            task_id = digest(a).to_ascii_uppercase();
+            task_id = digest(format!("{}{}", salt, a)).to_ascii_uppercase();
         },
         Err(e) => {
             error!("Error occured: {}", anyhow!(e));
@@ -190,35 +195,7 @@ pub fn prep_passthru_args(user_data: &Value, task_data: &Value) -> Option<(Strin
         }
     }
 
-    output_ps.map(|a| (format!("$taskData = '{}'\r\n$taskId = '{}'\r\n", a, &task_id), task_id))
-}
-
-/// Creates an http server with a path corresponsing to a hash of the task's source code.
-/// It was done like this in preparation for concurrent tasks, as a unique task ID was already needed for that.
-/// 
-/// Data is received by the http server via a JSON POST, and is assumed by Taskmaster to be report data.
-/// 
-/// Note that the http server listens forever, this coroutine should be joined with the process spawn.
-/// 
-/// TODO: Make this multithreaded and have the server kill itself once it has received a POST.
-pub async fn listen_for_report(task_id: &str, tx: Sender<Value>) -> Result<(), Box<dyn std::error::Error>> {
-    let tx_shared = Arc::new(Mutex::new(tx)); //hack so we can copy this to every instantiation of the closure
-    let route = warp::post()
-        .and(warp::path(task_id.to_owned()))
-        .and(warp::body::json())
-        .and_then(move |value: Value| {
-            let tx = tx_shared.clone();
-            async move { //Hack so move keyword doesn't consume the entire galaxy when we try to send
-                match tx.lock().await.send(value).await {
-                    Ok(_) => Ok::<_, warp::Rejection>(warp::reply::with_status("Report received.", StatusCode::OK)),
-                    Err(_) => Ok::<_, warp::Rejection>(warp::reply::with_status("Error occured while processing report.", StatusCode::INTERNAL_SERVER_ERROR))
-                }
-            }
-        }).boxed();
-
-    let _ = warp::serve(route).run(([127, 0, 0, 1], 3030)).await;
-
-    Ok(())
+    output_ps.map(|a| (backend.render_preamble(&a, &task_id, report_uri, report_token), task_id, parameters))
 }
 
 /// Retreives a relatively standardized task input from the user input serde structure.
@@ -243,10 +220,7 @@ fn tasks_from_seq(input: &Value) -> Option<Vec<Value>> {
 
 /// Returns a task input (serde Value) that is a Mapping and converts to a vec of Values.
 pub fn tasks_from_map(input: &Value) -> Option<Vec<Value>> {
-    let user_map = match input.as_mapping() {
-        Some(a) => a,
-        None => return None
-    };
+    let user_map = input.as_mapping()?;
 
     let mut tasks: Vec<Value> = Vec::new();
     
@@ -278,15 +252,489 @@ fn get_task_sequence(user_input: &Value) -> Option<Vec<Value>> {
     None // No other data structure is supported.
 }
 
-pub async fn execute_tasks(user_input: &Value, task_input: &Value) -> Result<()> {
-    let clean_user_input = sanitize_yaml(user_input).ok_or(anyhow!("Could not sanitize user input. This typically means malformed user input."))?;
+/// Options that apply to a whole run of tasks.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Time limit for the whole run. Tasks still running when it is reached are killed.
+    pub timeout: Option<Duration>,
+    /// Where to write the aggregated report of the run, if anywhere.
+    pub report: Option<PathBuf>,
+    /// What to do with the rest of the run once a task fails.
+    pub failure_policy: FailurePolicy,
+    /// Most tasks to run at the same time. 0 uses *DEFAULT_JOBS*.
+    pub jobs: usize,
+    /// ID of an earlier run to pick up where it left off, running only the tasks that did not succeed.
+    pub resume: Option<String>,
+    /// Variables given as "--var NAME=value", which win over every other source.
+    pub vars: Vec<(String, String)>,
+    /// Dotenv files to read variables from.
+    pub env_files: Vec<PathBuf>
+}
+
+/// Most tasks run at the same time when not told otherwise. Tasks mostly wait on the network or installers,
+/// so this is not tied to the number of CPUs.
+pub const DEFAULT_JOBS: usize = 4;
+
+/// What a run does once one of its tasks fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FailurePolicy {
+    /// Skip every remaining task. Tasks with "ContinueOnError" do not stop the run.
+    FailFast,
+    /// Run every remaining task regardless.
+    #[default]
+    KeepGoing,
+    /// Skip every remaining task like *FailFast*, then run the "+Undo" scripts of the tasks that succeeded, last
+    /// finished first.
+    Rollback
+}
+
+/// A user task resolved against its definition, ready to be run.
+struct PreparedTask {
+    /// Name of the task as written in its definition, e.g. "ExecBash".
+    name: String,
+    /// Position of the task in the run.
+    position: usize,
+    /// ID of the run the task is part of.
+    run_id: String,
+    /// The user's data for the task, without *RUNNER_KEYS*, with canonical parameter names and with defaults filled in.
+    user_data: Value,
+    /// Parameters the user left out that were given their "+Data" default.
+    defaults: Vec<String>,
+    /// The task's definition.
+    task_data: Value,
+    /// The task's script, without its preamble.
+    script_code: String,
+    /// Folder of the task's script, where the files it comes with are.
+    task_dir: PathBuf,
+    backend: Box<dyn ShellBackend>,
+    timeout: Option<Duration>,
+    retry: RetryPolicy,
+    report_required: bool,
+    /// Whether a failure of the task is ignored, rather than failing the run.
+    continue_on_error: bool,
+    /// Whether the script runs with admin privileges.
+    elevated: bool,
+    /// Whether this runs the task's "+Undo" script rather than its own.
+    undo: bool
+}
+
+impl PreparedTask {
+    /// Renders the script for one attempt at the task, returning the script, its task ID and its passthru data.
+    ///
+    /// Every attempt at every task of every run gets its own task ID, so identical tasks running at the same time get
+    /// their own reports, and a late report of an earlier attempt can't be mistaken for the current one.
+    fn render(&self, attempt: u32, report_uri: &str, report_token: &str) -> Option<(String, String, serde_json::Value)> {
+        let (preamble, task_id, parameters) = prep_passthru_args(
+            &self.user_data,
+            &self.task_data,
+            self.backend.as_ref(),
+            report_uri,
+            report_token,
+            &format!("{}/{}/{}{}:", self.run_id, self.position, if self.undo { "undo/" } else { "" }, attempt)
+        )?;
+
+        Some((self.backend.render_script(&preamble, &self.script_code), task_id, parameters))
+    }
+
+    /// The parameters that were given their "+Data" default along with their value, e.g. "elevated = false".
+    fn defaults_used(&self) -> Vec<String> {
+        self.defaults.iter()
+            .map(|a| format!("{} = {}", a, serde_json::to_string(&self.user_data.get(a)).unwrap_or_default()))
+            .collect()
+    }
+}
+
+/// How many times a task is tried before it counts as failed, and how long to wait in between.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RetryPolicy {
+    /// Total number of attempts, including the first.
+    attempts: u32,
+    /// Wait before the first retry.
+    delay: Duration,
+    /// Factor the wait is multiplied by after every retry.
+    backoff: f64
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { attempts: 1, delay: Duration::ZERO, backoff: 1.0 }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before the nth retry, counting from 1.
+    fn delay_before(&self, retry: u32) -> Duration {
+        let factor = self.backoff.powi(retry.saturating_sub(1).min(i32::MAX as u32) as i32);
+        Duration::try_from_secs_f64(self.delay.as_secs_f64() * factor).unwrap_or(Duration::MAX)
+    }
+}
+
+/// Returns a new ID for a run, made of its start time and a random suffix, e.g. "20240612-153045-3f9a1c".
+fn new_run_id() -> String {
+    let suffix = Uuid::new_v4().simple().to_string();
+    format!("{}-{}", Local::now().format("%Y%m%d-%H%M%S"), &suffix[..6])
+}
+
+/// Retreives the timeout of a task, preferring the user's "Timeout" over the "+Timeout" of its definition.
+///
+/// A timeout of 0 means no timeout, so "Timeout: 0" lets a task run past the "+Timeout" of its definition.
+fn task_timeout(user_data: &Value, task_data: &Value, task_name: &str) -> Option<Duration> {
+    for data in [user_data, task_data] {
+        let raw = match data.get("timeout") {
+            Some(Value::Null) | None => continue,
+            Some(a) => a
+        };
+
+        match duration_from_value(raw) {
+            Some(a) if a.is_zero() => return None,
+            Some(a) => return Some(a),
+            None => error!("Timeout {:?} of task \"{}\" is not a valid duration (e.g. 90, \"90s\", \"15m\", \"2h\"), ignoring it.", raw, task_name)
+        }
+    }
+
+    None
+}
+
+/// Retreives the retry policy of a task. The "+Retry" of its definition gives defaults, which the user's "Retry" overrides key by key.
+///
+/// Either may be a mapping of "Attempts", "Delay" and "Backoff", or just a number of attempts.
+fn task_retry(user_data: &Value, task_data: &Value, task_name: &str) -> RetryPolicy {
+    let mut policy = RetryPolicy::default();
+
+    for data in [task_data, user_data] {
+        let raw = match data.get("retry") {
+            Some(Value::Null) | None => continue,
+            Some(a) => a
+        };
+
+        if raw.is_number() {
+            match raw.as_u64().and_then(|a| u32::try_from(a).ok()) {
+                Some(a) if a >= 1 => policy.attempts = a,
+                _ => error!("Retry {:?} of task \"{}\" is not a number of attempts of at least 1, ignoring it.", raw, task_name)
+            }
+            continue
+        }
+
+        if !raw.is_mapping() {
+            error!("Retry {:?} of task \"{}\" should be a number of attempts, or have \"Attempts\", \"Delay\" and \"Backoff\", ignoring it.", raw, task_name);
+            continue
+        }
+
+        if let Some(a) = raw.get("attempts") {
+            match a.as_u64().and_then(|b| u32::try_from(b).ok()) {
+                Some(b) if b >= 1 => policy.attempts = b,
+                _ => error!("Retry attempts {:?} of task \"{}\" is not a number of at least 1, ignoring it.", a, task_name)
+            }
+        }
+
+        if let Some(a) = raw.get("delay") {
+            match duration_from_value(a) {
+                Some(b) => policy.delay = b,
+                None => error!("Retry delay {:?} of task \"{}\" is not a valid duration (e.g. 10, \"10s\", \"1m\"), ignoring it.", a, task_name)
+            }
+        }
+
+        if let Some(a) = raw.get("backoff") {
+            match a.as_f64() {
+                Some(b) if b >= 1.0 => policy.backoff = b,
+                _ => error!("Retry backoff {:?} of task \"{}\" is not a number of at least 1, ignoring it.", a, task_name)
+            }
+        }
+    }
+
+    policy
+}
+
+/// Whether a task must send a report to be considered successful, from "+ReportRequired" of its definition.
+///
+/// Defaults to true.
+fn report_required(task_data: &Value) -> bool {
+    task_data.get("reportrequired").and_then(|a| a.as_bool()).unwrap_or(true)
+}
+
+/// Returns a receiver that becomes true once Ctrl-C is pressed, along with the handle of the task listening for it.
+fn listen_for_interrupt() -> (watch::Receiver<bool>, JoinHandle<()>) {
+    let (tx, rx) = watch::channel(false);
+    let handle = tokio::spawn(async move {
+        if ctrl_c().await.is_ok() {
+            warn!("Interrupt received, stopping the run.");
+            let _ = tx.send(true);
+        }
+    });
+
+    (rx, handle)
+}
+
+/// Keys of a user task that configure how Taskmaster runs it, rather than being data for its script.
+///
+/// These are left out of the passthru data. Keys are compared in their sanitized form.
+const RUNNER_KEYS: [&str; 9] = ["timeout", "continueonerror", "retry", "id", "needs", "when", "foreach", "matrix", "as"];
+
+/// Returns the user data of a task without the keys in *RUNNER_KEYS*, and with parameters given by an alias renamed to
+/// the name the task's definition uses, e.g. "Bash" to "script" for ExecBash.
+fn script_user_data(user_data: &Value, task_data: &Value) -> Value {
+    let user_map = match user_data.as_mapping() {
+        Some(a) => a,
+        None => return user_data.clone()
+    };
+
+    let mut data = Mapping::new();
+    for (key, value) in user_map {
+        let key_str = match key.as_str() {
+            Some(a) if RUNNER_KEYS.contains(&a) => continue,
+            Some(a) => a,
+            None => {
+                data.insert(key.to_owned(), value.to_owned());
+                continue
+            }
+        };
+
+        let canonical = match get_by_path(task_data, &format!("{}/", key_str), None) {
+            Some((b, Value::String(c))) if b.is_mapping() && !user_map.contains_key(&c) => Value::String(c),
+            _ => key.to_owned()
+        };
+        data.insert(canonical, value.to_owned());
+    }
+
+    Value::Mapping(data)
+}
+
+/// Fills in the parameters the user left out or left empty with the "+Data" default of their definition, if it has one.
+/// Returns the names of the parameters that were filled in, in the order of the definition.
+///
+/// *user_data* is expected to have canonical parameter names, as returned by *script_user_data*.
+fn merge_defaults(user_data: &mut Value, task_data: &Value) -> Vec<String> {
+    let (user_map, task_map) = match (user_data.as_mapping_mut(), task_data.as_mapping()) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return Vec::new()
+    };
+
+    let mut filled = Vec::new();
+    for (key, spec) in task_map {
+        let default = match spec.get("data") {
+            Some(a) if !a.is_null() => a,
+            _ => continue
+        };
+        if user_map.get(key).is_some_and(|a| !a.is_null()) { continue }
+
+        user_map.insert(key.to_owned(), default.to_owned());
+        if let Some(a) = key.as_str() { filled.push(a.to_owned()) }
+    }
+
+    filled
+}
+
+/// Checks the sanitized user data of a task against its definition, once the runner's keys are left out, aliases
+/// are resolved and defaults filled in. *task_data* is the sanitized definition and *definition* the one as written.
+///
+/// Defaults are checked as well, so a "+Data" that doesn't fit its own "+Type" is caught.
+pub fn task_parameter_problems(user_data: &Value, task_data: &Value, definition: &Value) -> Vec<String> {
+    let mut user_data = script_user_data(user_data, task_data);
+    merge_defaults(&mut user_data, task_data);
+
+    check_parameters(&user_data, definition)
+}
+
+/// Whether a failure of a task should be ignored, from the user's "ContinueOnError".
+fn continue_on_error(user_data: &Value) -> bool {
+    user_data.get("continueonerror").and_then(|a| a.as_bool()).unwrap_or(false)
+}
+
+/// Whether a task's script should run with admin privileges, from its "Elevated" parameter once aliases are resolved
+/// and defaults filled in. Elevation is up to the shell, e.g. sudo for Bash, see *ShellBackend::command()*.
+fn elevated(script_data: &Value) -> bool {
+    script_data.get("elevated").and_then(|a| a.as_bool()).unwrap_or(false)
+}
+
+/// Resolves a user task against the task definitions, finding its script and rendering it with the task's passthru data.
+///
+/// Without *outputs*, as when planning a run, references to the outputs of other tasks are kept as written.
+async fn prepare_task(node: &TaskNode, context: &RunContext<'_>, outputs: Option<&TaskOutputs>) -> Result<PreparedTask> {
+    let user_task = &node.user_task;
+    let clean_task_input = context.clean_task_input;
+
+    // Tasks should only ever be hashtables, with 1 entry point
+    let user_map = user_task.as_mapping().ok_or(anyhow!("Task with the following data is not a Mapping/Hashtable: {:#?}", user_task))?;
+    if user_map.keys().len() != 1 {
+        return Err(anyhow!("Task with the following data has more than 1 entrypoint: {:#?}", user_task))
+    }
+
+    // Get entrypoint of task as string
+    let key_str = user_map.keys().next()
+        .and_then(|a| a.as_str())
+        .ok_or(anyhow!("Could not convert the name of task {:#?} to string.", user_task))?;
+
+    // Get real name of task based on user input task name.
+    let canonical_key = match get_by_path(clean_task_input, &format!("{}/", key_str), None) {
+        Some((_, b)) => b.as_str()
+            .ok_or(anyhow!("Found task name/alias \"{}\" but could not convert it to string.", key_str))?
+            .to_owned(),
+        None => return Err(anyhow!("Could not find task with name/alias \"{}\".", key_str))
+    };
+
+    // Get the first level of task data from the user input task.
+    // This should never fail as we got key_str from the user input task.
+    let user_data = user_task.get(Value::from(key_str))
+        .ok_or(anyhow!("Could not derive task data from task \"{key_str}\". This should not be possible."))?;
+
+    // Fill in "${...}" references before anything reads the user's data, including outputs of earlier tasks.
+    let lookup = |name: &str| match (name.strip_prefix("tasks."), outputs) {
+        (Some(a), Some(b)) => task_output(a, b),
+        (Some(_), None) => Some(Value::String(format!("${{{}}}", name))),
+        (None, _) => node.binding(name).or_else(|| context.variables.get(name))
+    };
+    let user_data = &interpolate(user_data, &lookup, "")?;
+
+    // Script names keep the casing of tasks.yaml, which matters on case sensitive filesystems.
+    let task_name = original_task_name(context.task_input, &canonical_key).unwrap_or(canonical_key.clone());
+
+    // Find the script for the user's task, the shell it runs under is picked from its extension.
+    let script_path: PathBuf = get_task_script(&task_name, &task_name, None)?;
+
+    // Check if the task actually has a reference {TASK NAME}.{EXTENSION} file
+    // This should be in the "tasks" directory
+    if !script_path.exists() {
+        return Err(anyhow!(
+            "Script for task \"{}\" could not be found at \"{}\". Please create this file and add code you would like to run for the task, and try again.",
+            key_str,
+            script_path.display()
+        ))
+    }
+
+    let backend = backend_for_script(&script_path)
+        .ok_or(anyhow!("No shell supports the script \"{}\" of task \"{}\".", script_path.display(), key_str))?;
+
+    // Read the code for the user's task to str.
+    let script_code = read_to_string(&script_path).await
+        .map_err(|e| anyhow!("Could not read the script \"{}\" of task \"{}\": {}", script_path.display(), key_str, e))?;
+    let task_dir = script_path.parent().map(|a| a.to_path_buf()).unwrap_or_default();
+
+    // Get inner task data for the referenced task as defined by tasks.yaml
+    let (task_data, _) = get_by_path(clean_task_input, format!("{}/", canonical_key).as_ref(), None)
+        .ok_or(anyhow!("Could not find task \"{canonical_key}\" in the task definitions. Please create a task with that name and try again."))?;
+
+    let mut script_data = script_user_data(user_data, &task_data);
+    let defaults = merge_defaults(&mut script_data, &task_data);
+
+    Ok(PreparedTask {
+        position: node.position,
+        run_id: context.run_id.to_owned(),
+        timeout: task_timeout(user_data, &task_data, &task_name),
+        retry: task_retry(user_data, &task_data, &task_name),
+        continue_on_error: continue_on_error(user_data),
+        report_required: report_required(&task_data),
+        elevated: elevated(&script_data),
+        user_data: script_data,
+        defaults,
+        name: task_name,
+        task_data,
+        script_code,
+        task_dir,
+        backend,
+        undo: false
+    })
+}
+
+/// Prepares the "+Undo" script of a task that succeeded, to be run with the same passthru data the task was given.
+/// Returns None if the task's definition has no "+Undo".
+async fn prepare_undo(node: &TaskNode, context: &RunContext<'_>, record: &TaskRecord) -> Result<Option<PreparedTask>> {
+    let mut task = prepare_task(node, context, None).await?;
+
+    let undo_name = match task.task_data.get("undo") {
+        None | Some(Value::Null) => return Ok(None),
+        Some(Value::String(a)) => a.to_owned(),
+        Some(a) => return Err(anyhow!("The +Undo of task \"{}\" should name a script in the tasks folder, not {:?}.", task.name, a))
+    };
+
+    let script_path = get_task_script(&undo_name, &task.name, None)?;
+    if !script_path.exists() {
+        return Err(anyhow!("The undo script \"{}\" of task \"{}\" could not be found at \"{}\".", undo_name, task.name, script_path.display()))
+    }
+
+    task.backend = backend_for_script(&script_path)
+        .ok_or(anyhow!("No shell supports the undo script \"{}\" of task \"{}\".", script_path.display(), task.name))?;
+    task.script_code = read_to_string(&script_path).await
+        .map_err(|e| anyhow!("Could not read the undo script \"{}\" of task \"{}\": {}", script_path.display(), task.name, e))?;
+    task.task_dir = script_path.parent().map(|a| a.to_path_buf()).unwrap_or_default();
+
+    // The passthru data is taken as is from the user's data, so this gives the script exactly what the task was given.
+    task.user_data = serde_yml::to_value(&record.parameters)?;
+    task.defaults.clear();
+    task.undo = true;
+
+    Ok(Some(task))
+}
+
+/// Runs the "+Undo" scripts of the tasks that succeeded, in the reverse of the order they finished in.
+///
+/// Returns the position of every task an undo was attempted for, along with the record of the undo.
+async fn roll_back(
+    nodes: &[TaskNode],
+    context: &RunContext<'_>,
+    records: &[Option<TaskRecord>],
+    finished: &[usize],
+    report_server: &ReportServer,
+    interrupt: watch::Receiver<bool>
+) -> Vec<(usize, TaskRecord)> {
+    info!("Rolling back the run, undoing the tasks that succeeded, last finished first.");
+
+    let mut undone: Vec<(usize, TaskRecord)> = Vec::new();
+    for position in finished.iter().rev() {
+        let record = match &records[*position] {
+            Some(a) if a.status == TaskStatus::Succeeded => a,
+            _ => continue
+        };
+        let node = &nodes[*position];
+
+        let mut undo = match prepare_undo(node, context, record).await {
+            Ok(Some(a)) => {
+                info!("Undoing task \"{}\".", node.label());
+                run_with_retries(&a, None, report_server, interrupt.clone()).await
+            },
+            Ok(None) => {
+                info!("Task \"{}\" has no +Undo, so it is left as it is.", node.label());
+                continue
+            },
+            Err(e) => {
+                let mut undo = TaskRecord::new(&record.name, "", record.parameters.clone());
+                undo.reason = Some(e.to_string());
+                undo
+            }
+        };
+
+        undo.id = node.id.clone();
+        undo.items = record_items(node);
+        if undo.status != TaskStatus::Succeeded {
+            error!("Could not undo task \"{}\": it {}{}.", node.label(), undo.status, undo.reason.as_ref().map(|a| format!(", {}", a)).unwrap_or_default());
+        }
+        undone.push((*position, undo));
+    }
+
+    undone
+}
+
+/// The tasks of a user file laid out as a graph, along with what they need to be prepared.
+struct LoadedRun {
+    variables: Variables,
+    clean_task_input: Value,
+    nodes: Vec<TaskNode>
+}
+
+/// Reads the variables and tasks of a user file, and checks them before anything is run.
+async fn load_run(user_input: &Value, task_input: &Value, options: &RunOptions) -> Result<LoadedRun> {
+    let variables = Variables::load(user_input, &options.env_files, &options.vars).await?;
+
+    let mut clean_user_input = sanitize_yaml(user_input).ok_or(anyhow!("Could not sanitize user input. This typically means malformed user input."))?;
     let clean_task_input = sanitize_yaml(task_input).ok_or(anyhow!("Could not sanitize task input. This typically means malformed task input."))?;
 
+    // The "Variables" block is not a task.
+    if let Some(a) = clean_user_input.as_mapping_mut() { a.remove("variables"); }
+
     // Get the tasks to run as defined by the user
     let tasks = match get_task_sequence(&clean_user_input) {
         Some(a) => a,
         None => {
-            let b = match get_by_path(&clean_user_input, "tasks", None) {
+            match get_by_path(&clean_user_input, "tasks", None) {
                 Some((c, _)) => {
                     match get_task_sequence(&c) {
                         Some(d) => d,
@@ -294,179 +742,795 @@ pub async fn execute_tasks(user_input: &Value, task_input: &Value) -> Result<()>
                     }
                 },
                 None => return Err(anyhow!("Could not get task sequence from user input."))
-            };
-            b
+            }
         }
     };
 
-    for user_task in tasks {
-        if !user_task.is_mapping() { // Tasks should only ever be hashtables
-            error!("Task with the following data is not a Mapping/Hashtable and was skipped: {:#?}", user_task);
-            continue
+    // Lay the tasks out as a graph first, so a broken graph stops the run before anything has run.
+    let nodes = build_task_graph(&tasks, &variables)?;
+    check_variables(&nodes, &variables)?;
+    check_task_parameters(&nodes, &variables, &clean_task_input, task_input)?;
+
+    Ok(LoadedRun { variables, clean_task_input, nodes })
+}
+
+/// What a task of a run would do, as shown by the "plan" command.
+pub struct PlannedTask {
+    /// Position of the task in the run, counting from 0.
+    pub position: usize,
+    /// Name of the task as the user wrote it.
+    pub label: String,
+    pub id: Option<String>,
+    /// Canonical name of the task, after resolving aliases.
+    pub name: String,
+    /// Name of the shell that would run the task.
+    pub shell: &'static str,
+    /// Whether the shell would run it with admin privileges.
+    pub elevated: bool,
+    /// Extension of the task's script, without the leading dot.
+    pub extension: &'static str,
+    /// Task ID of the first attempt.
+    pub task_id: String,
+    /// Passthru data handed to the script.
+    pub parameters: serde_json::Value,
+    /// The final script handed to the shell.
+    pub script: String,
+    /// Names of the tasks it waits for.
+    pub waits_for: Vec<String>,
+    /// Item variables of this invocation, if the task is expanded by "ForEach" or "Matrix", e.g. "item = 'a'".
+    pub items: Vec<String>,
+    /// Its "When", as written.
+    pub when: Option<String>,
+    /// Parameters the user left out that were given their "+Data" default, e.g. "elevated = false".
+    pub defaults: Vec<String>
+}
+
+/// Report URI and token put in planned scripts in place of the ones a run's report server would have.
+const PLAN_REPORT_URI: &str = "<report-uri>";
+const PLAN_REPORT_TOKEN: &str = "<report-token>";
+
+/// Resolves every task of a user file as a run would, without running anything.
+///
+/// Returns the ID the planned run was given along with each task, or why it could not be resolved. Task IDs are
+/// salted with the run ID, so a real run of the same file gives its tasks other IDs. References to the outputs of
+/// other tasks are left as written, as are the report URI and token.
+pub async fn plan_tasks(user_input: &Value, task_input: &Value, options: &RunOptions) -> Result<(String, Vec<Result<PlannedTask>>)> {
+    let loaded = load_run(user_input, task_input, options).await?;
+    let run_id = new_run_id();
+    let context = RunContext {
+        run_id: &run_id,
+        clean_task_input: &loaded.clean_task_input,
+        task_input,
+        variables: &loaded.variables
+    };
+
+    let mut planned = Vec::new();
+    for node in &loaded.nodes {
+        let task = prepare_task(node, &context, None).await
+            .and_then(|a| {
+                let (script, task_id, parameters) = a.render(1, PLAN_REPORT_URI, PLAN_REPORT_TOKEN)
+                    .ok_or(anyhow!("its passthru data could not be prepared"))?;
+
+                Ok(PlannedTask {
+                    position: node.position,
+                    label: task_label(&node.user_task, node.position),
+                    id: node.id.clone(),
+                    name: a.name.clone(),
+                    shell: a.backend.name(),
+                    elevated: a.elevated,
+                    extension: a.backend.extension(),
+                    task_id,
+                    parameters,
+                    script,
+                    waits_for: waits_for(node, &loaded.nodes),
+                    items: node.bindings.iter()
+                        .map(|(b, c)| format!("{} = {}", b, serde_json::to_string(c).unwrap_or_default()))
+                        .collect(),
+                    when: node.when.as_ref().map(|b| b.source.clone()),
+                    defaults: a.defaults_used()
+                })
+            })
+            .map_err(|e| anyhow!("Task \"{}\" (number {}) could not be planned: {}", node.label(), node.position + 1, e));
+
+        planned.push(task);
+    }
+
+    Ok((run_id, planned))
+}
+
+/// Names of the tasks a task waits for, in the order they are listed.
+fn waits_for(node: &TaskNode, nodes: &[TaskNode]) -> Vec<String> {
+    let mut positions: Vec<usize> = Vec::new();
+    for a in node.needs.iter().chain(&node.after) {
+        if !positions.contains(a) { positions.push(*a) }
+    }
+
+    positions.iter().map(|a| nodes[*a].label()).collect()
+}
+
+pub async fn execute_tasks(user_input: &Value, task_input: &Value, options: &RunOptions) -> Result<()> {
+    let LoadedRun { variables, clean_task_input, nodes } = load_run(user_input, task_input, options).await?;
+
+    let jobs = match options.jobs {
+        0 => DEFAULT_JOBS,
+        a => a
+    };
+
+    let ids: HashMap<String, usize> = nodes.iter()
+        .filter_map(|a| a.id.as_ref().map(|b| (b.to_owned(), a.position)))
+        .collect();
+
+    // Records by position in the run, filled in as tasks finish or are skipped.
+    let mut records: Vec<Option<TaskRecord>> = vec![None; nodes.len()];
+    let mut scheduled = vec![false; nodes.len()];
+    // Positions of the tasks that were run, in the order they finished in.
+    let mut finished: Vec<usize> = Vec::new();
+
+    let input_hash = run_input_hash(user_input, &variables, task_input)?;
+    let mut state = match &options.resume {
+        None => {
+            let state = RunState { run_id: new_run_id(), started: Local::now(), input_hash, tasks: Vec::new() };
+            info!("Starting run \"{}\" of {} task(s), running up to {} at once.", state.run_id, nodes.len(), jobs);
+            state
+        },
+        Some(a) => {
+            let state = RunState::load(a).await?;
+            if state.input_hash != input_hash {
+                return Err(anyhow!(
+                    "The task file, its variables or the task definitions have changed since run \"{}\" started, so it can not be resumed. Start a new run instead.", a
+                ))
+            }
+            if state.tasks.len() != nodes.len() {
+                return Err(anyhow!("Run \"{}\" had {} task(s) but the task file now gives {}, so it can not be resumed. Start a new run instead.", a, state.tasks.len(), nodes.len()))
+            }
+
+            // Only tasks that succeeded are kept, everything else runs again.
+            for (position, record) in state.tasks.iter().enumerate() {
+                if let Some(b) = record && b.status == TaskStatus::Succeeded {
+                    records[position] = Some(b.to_owned());
+                    scheduled[position] = true;
+                    finished.push(position);
+                }
+            }
+
+            let done = scheduled.iter().filter(|b| **b).count();
+            info!("Resuming run \"{}\", {} of {} task(s) already succeeded, running up to {} at once.", a, done, nodes.len(), jobs);
+            state
         }
+    };
+    let run_id = state.run_id.clone();
+    let started = state.started;
 
-        // Tasks should only have 1 entry point
-        let user_map = user_task.as_mapping().ok_or(anyhow!(format!("Task with the following data is not valid: {:#?}", user_task)))?;
-        if user_map.keys().len() != 1 {
-            error!("Task with the following data had more than 1 entrypoint and was skipped: {:#?}", user_task);
-            continue
+    let run_deadline = match options.timeout {
+        Some(a) => Some(Instant::now().checked_add(a).ok_or(anyhow!("The run timeout of {}s is too long.", a.as_secs_f64()))?),
+        None => None
+    };
+
+    // One report server for the whole run, tasks are told its address and token through their preamble.
+    let report_server = ReportServer::start().await?;
+    let (interrupt, interrupt_handle) = listen_for_interrupt();
+    let context = RunContext {
+        run_id: &run_id,
+        clean_task_input: &clean_task_input,
+        task_input,
+        variables: &variables
+    };
+
+    let mut running = FuturesUnordered::new();
+
+    // Once set, every task that has not started yet is skipped for this reason.
+    let mut stopped_early: Option<String> = None;
+
+    loop {
+        if stopped_early.is_none() && *interrupt.borrow() {
+            stopped_early = Some("the run was interrupted".to_owned());
         }
 
-        // Get entrypoint of task as string
-        let key_str = match user_map.keys().next() {
-            Some(a) => {
-                match a.as_str() {
-                    Some(b) => b,
-                    None => {
-                        error!("Could not convert {:#?} to string, skipping task.", a);
-                        continue
+        if stopped_early.is_none() && run_deadline.is_some_and(|a| Instant::now() >= a) {
+            stopped_early = Some("the run ran past its timeout".to_owned());
+        }
+
+        // Skipping a task can block the tasks that need it, so keep going until nothing changes.
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for node in &nodes {
+                if scheduled[node.position] { continue }
+
+                let readiness = match &stopped_early {
+                    Some(a) => Readiness::Blocked(a.to_owned()),
+                    None => readiness(node, &nodes, &ids, &records, &variables)
+                };
+
+                match readiness {
+                    Readiness::Waiting => continue,
+                    Readiness::Ready if running.len() >= jobs => continue,
+                    Readiness::Ready => {
+                        let outputs = task_outputs(&ids, &records);
+                        running.push(run_node(node, &context, outputs, run_deadline, &report_server, interrupt.clone()));
+                    },
+                    Readiness::Blocked(reason) => {
+                        let mut record = TaskRecord::skipped(&record_name(node, &context), &reason);
+                        record.id = node.id.clone();
+                        record.items = record_items(node);
+                        records[node.position] = Some(record);
+                        changed = true;
+                    },
+                    Readiness::Broken(reason) => {
+                        error!("Task \"{}\" failed, as {}.", node.label(), reason);
+                        let mut record = TaskRecord::new(&record_name(node, &context), "", serde_json::Value::Null);
+                        record.id = node.id.clone();
+                        record.items = record_items(node);
+                        record.reason = Some(reason);
+                        if stopped_early.is_none() { stopped_early = stop_reason(&record, options.failure_policy) }
+                        records[node.position] = Some(record);
+                        changed = true;
                     }
                 }
-            },
-            None => continue
-        };
 
-        // Get real name of task based on user input task name.
-        let canonical_key = match get_by_path(&clean_task_input, &format!("{}/", key_str), None) {
-            Some((_, b)) => match b.as_str() {
-                Some(c) => {
-                    c.to_owned()
-                },
-                None => {
-                    error!("Found task name/alias \"{}\" but could not convert it to string.", key_str);
-                    continue
-                } 
-            },
-            None => {
-                error!("Could not find task with name/alias \"{}\", skipping task.", key_str);
-                continue
+                scheduled[node.position] = true;
             }
-        };
+        }
 
-        // Get the first level of task data from the user input task.
-        // This should never fail as we got key_str from the user input task.
-        let user_data = match user_task.get(Value::from(key_str)) {
+        // Nothing is running and nothing more could be started, every task is accounted for.
+        let (position, record) = match running.next().await {
             Some(a) => a,
-            None => {
-                error!("Could not derive task data from task \"{key_str}\". This should not be possible, skipping task.");
-                continue
-            }
+            None => break
         };
 
-        // Creates a temporary script to run based on the current task specified by the user..
-        let ps_file_pathbuf: PathBuf = get_task_script(&canonical_key, None)?;
-        let ps_file_path = match ps_file_pathbuf.to_str() {
-            Some(a) => a,
-            None => {
-                error!("Could not convert task code of \"{}\" to string. Skipping task.", key_str);
-                continue
-            }
+        if stopped_early.is_none() { stopped_early = stop_reason(&record, options.failure_policy) }
+
+        records[position] = Some(record);
+        finished.push(position);
+        save_state(&mut state, &records).await;
+    }
+
+    drop(running);
+    save_state(&mut state, &records).await;
+
+    // Under the rollback policy, a failed run undoes what its tasks did.
+    let run_failed = stopped_early.is_some() || records.iter().flatten().any(|a| a.status.is_failure() && !a.continue_on_error);
+    let mut rollback: Vec<TaskRecord> = Vec::new();
+    if options.failure_policy == FailurePolicy::Rollback && run_failed {
+        if *interrupt.borrow() {
+            warn!("The run was interrupted, so nothing is rolled back.");
+        } else {
+            let undone = roll_back(&nodes, &context, &records, &finished, &report_server, interrupt.clone()).await;
+
+            // Undone tasks have to run again if the run is resumed.
+            let mut kept = records.clone();
+            for (position, _) in &undone { kept[*position] = None }
+            save_state(&mut state, &kept).await;
+
+            rollback = undone.into_iter().map(|(_, a)| a).collect();
+        }
+    }
+
+    let records: Vec<TaskRecord> = records.into_iter().flatten().collect();
+
+    interrupt_handle.abort();
+    report_server.shutdown().await;
+
+    log_summary(&records);
+
+    let failed_undos = rollback.iter().filter(|a| a.status != TaskStatus::Succeeded).count();
+    if !rollback.is_empty() {
+        info!("Rollback summary: {} task(s) undone, {} could not be undone.", rollback.len() - failed_undos, failed_undos);
+    }
+
+    // The run report covers every task, including those that were skipped.
+    if let Some(path) = &options.report {
+        let run_report = RunReport {
+            run_id: run_id.clone(),
+            started,
+            finished: Local::now(),
+            host: whoami::fallible::hostname().unwrap_or_default(),
+            user: whoami::username(),
+            tasks: records.clone(),
+            rollback
         };
-        
-        // Check if the task actually has a reference {TASK NAME}.ps1 file\
-        // This should be in the ".\tasks" directory
-        match Path::new(ps_file_path).exists() {
-            true => {},
-            false => {
-                error!(
-                    "PowerShell code for task \"{}\" could not be found at \"{}\". Please create this file and add code you would like to run for the task, and try again. Skipping task.",
-                    key_str, 
-                    ps_file_path
-                );
-                continue
-            }
+
+        match write_run_report(&run_report, path).await {
+            Ok(_) => info!("Wrote the report of run \"{}\" to \"{}\".", run_id, path.display()),
+            Err(e) => error!("Could not write the report of run \"{}\" to \"{}\": {}", run_id, path.display(), e)
         }
+    }
 
-        // Read the code for the user's task to str.
-        let ps_code = read_to_string(ps_file_path).await?;
+    let failed_tasks: Vec<String> = records.iter()
+        .filter(|a| a.status.is_failure() && !a.continue_on_error)
+        .map(|a| match a.status {
+            TaskStatus::Failed => a.name.clone(),
+            b => format!("{} ({})", a.name, b)
+        })
+        .collect();
+
+    let mut problems: Vec<String> = Vec::new();
+    if !failed_tasks.is_empty() {
+        problems.push(format!("{} task(s) failed: {}", failed_tasks.len(), failed_tasks.join(", ")));
+    }
 
-        // Get inner task data for the referenced task as defined by tasks.yaml
-        let (task_data, _) = match get_by_path(&clean_task_input, format!("{}/", canonical_key).as_ref(), None) {
-            Some(a) => a,
-            None => {
-                error!("Could not find task \"{canonical_key}\" in tasks.yaml. Please create a task with that name and try again.");
-                continue
-            }
+    if let Some(a) = stopped_early {
+        problems.push(format!("the run was stopped early as {}", a));
+    }
+
+    if failed_undos > 0 {
+        problems.push(format!("{} task(s) could not be undone", failed_undos));
+    }
+
+    if !problems.is_empty() {
+        info!("Run \"{}\" can be picked up where it left off with \"run --resume {}\".", run_id, run_id);
+        return Err(anyhow!(problems.join("; ")))
+    }
+
+    Ok(())
+}
+
+/// Saves the records of a run so far, so it can be resumed. A run goes on even if its state can't be saved.
+async fn save_state(state: &mut RunState, records: &[Option<TaskRecord>]) {
+    state.tasks = records.to_vec();
+    if let Err(e) = state.save().await {
+        warn!("Could not save the state of run \"{}\", it won't be possible to resume it: {}", state.run_id, e);
+    }
+}
+
+/// Checks the "${...}" references of every task before anything runs, so a typo can't leave a run half done.
+fn check_variables(nodes: &[TaskNode], variables: &Variables) -> Result<()> {
+    let problems: Vec<String> = nodes.iter()
+        .filter_map(|node| {
+            let data = node.user_task.as_mapping()?.values().next()?;
+            // Outputs of other tasks are only known once those tasks ran, the graph already checked their Ids.
+            let lookup = |name: &str| match name.starts_with("tasks.") {
+                true => Some(Value::Null),
+                false => node.binding(name).or_else(|| variables.get(name))
+            };
+            let e = interpolate(data, &lookup, "").err()?;
+            Some(format!("task \"{}\" (number {}) uses an {}", node.label(), node.position + 1, e))
+        })
+        .collect();
+
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(anyhow!("Nothing was run, as {}.", problems.join("; ")))
+    }
+}
+
+/// Checks the parameters of every task against the "+Type", "+Required" and "+Items" of its definition, failing with
+/// every problem found if there are any. Tasks that can't be resolved are left to fail when they are run.
+fn check_task_parameters(nodes: &[TaskNode], variables: &Variables, clean_task_input: &Value, task_input: &Value) -> Result<()> {
+    let mut problems: Vec<String> = Vec::new();
+
+    for node in nodes {
+        let (key, data) = match node.user_task.as_mapping().and_then(|a| a.iter().next()) {
+            Some((a, b)) => (a.as_str().unwrap_or_default(), b),
+            None => continue
         };
 
-        // Get edited source code + hash of source code for the task ps1 to run.
-        // This ensures code can't be modified JIT by some nefarious process or silly evaluation on a task.
-        // This was also done in preparation for parallel tasks which is no longer planned.
-        let (t_source, t_hash) = match prep_passthru_args(user_data, &task_data) {
-            Some((a, b)) => (a, b.to_ascii_uppercase()),
-            None => return Err(anyhow!("Error occured when initializing task data."))
+        let (task_data, canonical_key) = match get_by_path(clean_task_input, &format!("{}/", key), None) {
+            Some((a, Value::String(b))) => (a, b),
+            _ => continue
+        };
+        let definition = match get_by_path(task_input, &format!("{}/", canonical_key), None) {
+            Some((a, _)) => a,
+            None => continue
+        };
+
+        // Outputs of other tasks are only known once those tasks ran, so they are left as written and not checked.
+        let lookup = |name: &str| match name.starts_with("tasks.") {
+            true => Some(Value::String(format!("${{{}}}", name))),
+            false => node.binding(name).or_else(|| variables.get(name))
+        };
+        let user_data = match interpolate(data, &lookup, "") {
+            Ok(a) => a,
+            Err(_) => continue
+        };
+
+        for problem in task_parameter_problems(&user_data, &task_data, &definition) {
+            problems.push(format!("task \"{}\" (number {}): {}", node.label(), node.position + 1, problem));
+        }
+    }
+
+    match problems.len() {
+        0 => Ok(()),
+        a => Err(anyhow!("Nothing was run, as {} problem(s) were found with the parameters of the tasks:\n  {}", a, problems.join("\n  ")))
+    }
+}
+
+/// Whether a task of the run can start yet.
+enum Readiness {
+    /// Every task it waits for has finished well enough, and its condition holds.
+    Ready,
+    /// Some task it waits for has not finished yet.
+    Waiting,
+    /// It will never start, for the given reason.
+    Blocked(String),
+    /// It fails without starting, for the given reason.
+    Broken(String)
+}
+
+/// Checks the tasks a task waits for, then its "When". Only the tasks it needs can block it, unless they failed but
+/// are set to continue on error.
+fn readiness(
+    node: &TaskNode,
+    nodes: &[TaskNode],
+    ids: &HashMap<String, usize>,
+    records: &[Option<TaskRecord>],
+    variables: &Variables
+) -> Readiness {
+    let mut waiting = node.after.iter().any(|a| records[*a].is_none());
+
+    for need in &node.needs {
+        match &records[*need] {
+            None => waiting = true,
+            Some(a) if a.status == TaskStatus::Succeeded => {},
+            Some(a) if a.status.is_failure() && a.continue_on_error => {},
+            Some(a) if a.status == TaskStatus::Skipped => {
+                return Readiness::Blocked(format!("it needs task \"{}\", which was skipped", nodes[*need].label()))
+            },
+            Some(a) => return Readiness::Blocked(format!("it needs task \"{}\", which {}", nodes[*need].label(), a.status))
+        }
+    }
+
+    if waiting { return Readiness::Waiting }
+
+    let condition = match &node.when {
+        Some(a) => a,
+        None => return Readiness::Ready
+    };
+
+    let resolve = |name: &[String]| {
+        if let Some(a) = node.binding(&name.join(".")) { return ExprValue::from_yaml(&a) }
+
+        match name {
+            [a, b] if a.eq_ignore_ascii_case("vars") => match variables.get(b) {
+                Some(c) => ExprValue::from_yaml(&c),
+                None => Ok(ExprValue::Null)
+            },
+            _ => task_fact(name, ids, records)
+        }
+    };
+
+    match condition.expr.evaluate(&resolve) {
+        Ok(a) if a.is_truthy() => Readiness::Ready,
+        Ok(_) => Readiness::Blocked(format!("its condition '{}' was false", condition.source)),
+        Err(e) => Readiness::Broken(format!("its condition '{}' could not be checked: {}", condition.source, e))
+    }
+}
+
+/// Looks up `tasks.<id>.<fact>` for a "When": status, succeeded, failed, skipped or exitcode.
+fn task_fact(name: &[String], ids: &HashMap<String, usize>, records: &[Option<TaskRecord>]) -> Result<ExprValue> {
+    let (id, fact) = match name {
+        [a, b, c, d] if a.eq_ignore_ascii_case("tasks") && c.eq_ignore_ascii_case("outputs") => {
+            let value = task_output(&format!("{}.outputs.{}", b, d), &task_outputs(ids, records))
+                .ok_or(anyhow!("Task \"{}\" has not finished, or did not output \"{}\".", b, d))?;
+            return ExprValue::from_yaml(&value)
+        },
+        [a, b, c] if a.eq_ignore_ascii_case("tasks") => (b, c.to_ascii_lowercase()),
+        [a, ..] if a.eq_ignore_ascii_case("tasks") => {
+            return Err(anyhow!("Use \"tasks.<Id>.status\" or \"tasks.<Id>.outputs.<name>\" to check on an earlier task, not \"{}\".", name.join(".")))
+        },
+        _ => return Err(anyhow!("Unknown name \"{}\", expected env.<NAME>, vars.<name>, host.<fact>, tasks.<Id>.<fact> or exists(path).", name.join(".")))
+    };
+
+    let record = ids.get(id)
+        .and_then(|a| records[*a].as_ref())
+        .ok_or(anyhow!("Task \"{}\" has not finished, or no task has that Id.", id))?;
+
+    match fact.as_str() {
+        "status" => Ok(ExprValue::Text(record.status.to_string())),
+        "succeeded" => Ok(ExprValue::Bool(record.status == TaskStatus::Succeeded)),
+        "failed" => Ok(ExprValue::Bool(record.status.is_failure())),
+        "skipped" => Ok(ExprValue::Bool(record.status == TaskStatus::Skipped)),
+        "exitcode" => Ok(record.outcome.as_ref().and_then(|a| a.exit_code).map(|a| ExprValue::Number(a as f64)).unwrap_or(ExprValue::Null)),
+        _ => Err(anyhow!("Unknown fact \"{}\" of task \"{}\", expected status, succeeded, failed, skipped or exitcode.", fact, id))
+    }
+}
+
+/// Outputs of the finished tasks that have an Id, by Id.
+type TaskOutputs = HashMap<String, serde_json::Map<String, serde_json::Value>>;
+
+/// Collects the outputs the finished tasks of the run reported so far.
+fn task_outputs(ids: &HashMap<String, usize>, records: &[Option<TaskRecord>]) -> TaskOutputs {
+    ids.iter()
+        .filter_map(|(id, position)| {
+            let report = records[*position].as_ref()?.report.as_ref()?;
+            Some((id.to_owned(), report.outputs.to_owned()))
+        })
+        .collect()
+}
+
+/// Looks up "<id>.outputs.<name>", the rest of a "${tasks.<id>.outputs.<name>}" reference.
+/// Output names are matched ignoring case if there is no exact match.
+fn task_output(reference: &str, outputs: &TaskOutputs) -> Option<Value> {
+    let (id, rest) = reference.split_once('.')?;
+    let name = rest.strip_prefix("outputs.")?;
+    let task_outputs = outputs.get(id)?;
+
+    let value = task_outputs.get(name)
+        .or_else(|| task_outputs.iter().find(|(a, _)| a.eq_ignore_ascii_case(name)).map(|(_, b)| b))?;
+    serde_yml::to_value(value).ok()
+}
+
+/// Why the rest of a run should be skipped after a task ended, if it should be.
+fn stop_reason(record: &TaskRecord, policy: FailurePolicy) -> Option<String> {
+    match record.status {
+        TaskStatus::Succeeded | TaskStatus::Skipped => None,
+        TaskStatus::Interrupted => Some("the run was interrupted".to_owned()),
+        _ if record.continue_on_error => {
+            warn!("Task \"{}\" failed, continuing as it is set to continue on error.", record.name);
+            None
+        },
+        _ if policy == FailurePolicy::FailFast => {
+            Some(format!("task \"{}\" failed and the run is set to fail fast", record.id.as_ref().unwrap_or(&record.name)))
+        },
+        _ if policy == FailurePolicy::Rollback => {
+            Some(format!("task \"{}\" failed and the run is set to roll back", record.id.as_ref().unwrap_or(&record.name)))
+        },
+        _ => None
+    }
+}
+
+/// What every task of a run is prepared and run with.
+struct RunContext<'a> {
+    run_id: &'a str,
+    clean_task_input: &'a Value,
+    task_input: &'a Value,
+    variables: &'a Variables
+}
+
+/// Prepares and runs a task of the run, returning its position in the run along with its record.
+///
+/// *outputs* are the outputs of the tasks that had finished when this one started.
+async fn run_node(
+    node: &TaskNode,
+    context: &RunContext<'_>,
+    outputs: TaskOutputs,
+    run_deadline: Option<Instant>,
+    report_server: &ReportServer,
+    interrupt: watch::Receiver<bool>
+) -> (usize, TaskRecord) {
+    let mut record = match prepare_task(node, context, Some(&outputs)).await {
+        Ok(a) => run_with_retries(&a, run_deadline, report_server, interrupt).await,
+        Err(e) => {
+            error!("Could not prepare task \"{}\": {}", node.label(), e);
+
+            let mut record = TaskRecord::new(&record_name(node, context), "", serde_json::Value::Null);
+            record.reason = Some(e.to_string());
+            record.continue_on_error = node.user_task.as_mapping()
+                .and_then(|a| a.values().next())
+                .is_some_and(continue_on_error);
+            record
+        }
+    };
+
+    record.id = node.id.clone();
+    record.items = record_items(node);
+    (node.position, record)
+}
+
+/// Runs a prepared task as many times as its retry policy allows, until an attempt succeeds.
+///
+/// The record of the last attempt is returned, with every earlier attempt in its "previous_attempts".
+async fn run_with_retries(
+    task: &PreparedTask,
+    run_deadline: Option<Instant>,
+    report_server: &ReportServer,
+    mut interrupt: watch::Receiver<bool>
+) -> TaskRecord {
+    let mut previous_attempts: Vec<AttemptRecord> = Vec::new();
+    let mut attempt: u32 = 1;
+
+    loop {
+        // Whichever comes first out of the task's own timeout and the time left in the run.
+        let remaining = run_deadline.map(|a| a.saturating_duration_since(Instant::now()));
+        let timeout = match (task.timeout, remaining) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b)
         };
 
-        let task_code = format!("{}{}", &t_source, ps_code);
+        let mut record = run_task(task, attempt, timeout, report_server, interrupt.clone()).await;
 
-        // Set up a channel for async communication, this is purely within Taskmaster.
-        let (tx, mut rx) = tokio::sync::mpsc::channel::<Value>(10);
+        let retryable = matches!(record.status, TaskStatus::Failed | TaskStatus::TimedOut);
+        if !retryable || attempt >= task.retry.attempts {
+            record.previous_attempts = previous_attempts;
+            return record
+        }
+
+        // Don't bother waiting for a retry that the run's timeout would not leave any time for.
+        let delay = task.retry.delay_before(attempt);
+        let remaining = run_deadline.map(|a| a.saturating_duration_since(Instant::now()));
+        if remaining.is_some_and(|a| a <= delay) {
+            warn!("Task \"{}\" failed on attempt {} of {}, but the run has no time left to retry it.", task.name, attempt, task.retry.attempts);
+            record.previous_attempts = previous_attempts;
+            return record
+        }
+
+        warn!(
+            "Task \"{}\" {} on attempt {} of {}, retrying in {:.1}s.",
+            task.name, record.status, attempt, task.retry.attempts, delay.as_secs_f64()
+        );
+
+        previous_attempts.push(record.into_attempt());
+        attempt += 1;
 
-        // Set up a report listener for powershell-side (HTTP on localhost), and the powershell process spawner.
         tokio::select! {
-            output = spawn_powershell(&task_code, false) => {
-                info!("Output: {}", output?)
-            }
-    
-            _ = listen_for_report(&t_hash, tx) => {
-                error!("Report server failed.")
+            _ = tokio::time::sleep(delay) => {},
+            Ok(_) = interrupt.wait_for(|a| *a) => {
+                // The interrupted attempt is never started, but the run still needs to see it was interrupted.
+                let mut record = TaskRecord::new(&task.name, "", serde_json::Value::Null);
+                record.status = TaskStatus::Interrupted;
+                record.attempt = attempt;
+                record.continue_on_error = task.continue_on_error;
+                record.reason = Some(format!("the run was interrupted while waiting to retry, after {} attempt(s)", attempt - 1));
+                record.previous_attempts = previous_attempts;
+                return record
             }
         }
+    }
+}
 
-        // Return report back so it can be logged.
-        let report = match rx.recv().await {
-            Some(a) => a,
-            None => return Err(anyhow!("Error occured when retreiving report thread data."))
-        };
+/// Runs one attempt at a prepared task and waits for its report, returning what happened as a record.
+///
+/// *timeout* replaces the task's own timeout, so it can be cut short by the time left in the run.
+async fn run_task(task: &PreparedTask, attempt: u32, timeout: Option<Duration>, report_server: &ReportServer, interrupt: watch::Receiver<bool>) -> TaskRecord {
+    let name = &task.name;
+
+    // Get edited source code + hash of source code for the task ps1 to run.
+    // This ensures code can't be modified JIT by some nefarious process or silly evaluation on a task.
+    let (code, id, parameters) = match task.render(attempt, &report_server.uri, &report_server.token) {
+        Some(a) => a,
+        None => {
+            error!("Error occured when initializing the data of task \"{name}\".");
+            let mut record = TaskRecord::new(name, "", serde_json::Value::Null);
+            record.continue_on_error = task.continue_on_error;
+            record.reason = Some("its passthru data could not be prepared".to_owned());
+            return record
+        }
+    };
+    let id = &id;
+
+    let mut record = TaskRecord::new(name, id, parameters);
+    record.continue_on_error = task.continue_on_error;
+    record.attempt = attempt;
 
-        // Log report.
-        info!("Report for task \"{canonical_key}\" of hash \"{t_hash}\": {:?}", report);
+    info!("Running task \"{name}\" of hash \"{id}\" with {}.", task.backend.name());
+    if attempt == 1 && !task.defaults.is_empty() {
+        info!("Task \"{name}\" uses the defaults of its definition for: {}.", task.defaults_used().join(", "));
     }
 
-    Ok(())
-}
+    // Route reports posted for this task's ID to a channel, this is purely within Taskmaster.
+    let mut rx = report_server.register(id, name);
 
-/// Retreives the task definitions file and the contents of said file.
-/// 
-/// # Panic
-/// This function will panic if it cannot get the task definitions file.
-/// 
-/// This is intended as the entire tool relies on this functionality, and therefore there is no recovery if this happens.
-pub async fn task_defs_contents() -> Result<String> {
-    let task_input_path = match get_task_script("tasks", Some("yaml")) {
+    let outcome = match spawn_script(task.backend.as_ref(), &code, &task.task_dir, id, task.elevated, timeout, interrupt).await {
         Ok(a) => a,
         Err(e) => {
-            panic!("Error occured when retreiving \"tasks\\tasks.yaml\": {}", anyhow!(e))
+            error!("Task \"{name}\" of hash \"{id}\" could not be run: {}", e);
+            report_server.unregister(id);
+            return record
         }
     };
-    file_contents(&task_input_path).await
+
+    log_outcome(name, &outcome);
+
+    if outcome.termination != Termination::Exited {
+        report_server.unregister(id);
+    }
+
+    match outcome.termination {
+        Termination::TimedOut => {
+            error!("Task \"{name}\" of hash \"{id}\" timed out and was killed.");
+            record.status = TaskStatus::TimedOut;
+            record.outcome = Some(outcome);
+            return record
+        },
+        Termination::Interrupted => {
+            error!("Task \"{name}\" of hash \"{id}\" was interrupted and killed.");
+            record.status = TaskStatus::Interrupted;
+            record.outcome = Some(outcome);
+            return record
+        },
+        Termination::Exited => {}
+    }
+
+    // A non-zero exit fails the task whether or not it managed to post a report.
+    let mut failed = !outcome.success();
+    if failed {
+        error!("Task \"{name}\" of hash \"{id}\" failed with {}.", outcome.exit_description());
+    }
+
+    // Return report back so it can be logged, allowing for a report that arrives just after the process exits.
+    let report = match receive_report(report_server, id, &mut rx, REPORT_GRACE).await {
+        Some(a) => a,
+        None if task.report_required => {
+            error!("Task \"{name}\" of hash \"{id}\" did not send a report, which its definition requires.");
+            record.outcome = Some(outcome);
+            return record
+        },
+        None => {
+            info!("Task \"{name}\" of hash \"{id}\" did not send a report, building one from its output.");
+            report_from_outcome(&outcome)
+        }
+    };
+
+    log_report(name, id, &report);
+
+    // The task's own verdict counts as much as its exit code.
+    if report.status == ReportStatus::Failure && !failed {
+        error!("Task \"{name}\" of hash \"{id}\" reported that it failed.");
+        failed = true;
+    }
+
+    if !failed { record.status = TaskStatus::Succeeded }
+    record.outcome = Some(outcome);
+    record.report = Some(report);
+    record
+}
+
+/// Logs the captured output and exit status of a task's process.
+fn log_outcome(task_name: &str, outcome: &TaskOutcome) {
+    info!(
+        "Task \"{}\" exited with {} after {:.2}s.",
+        task_name,
+        outcome.exit_description(),
+        outcome.duration.as_secs_f64()
+    );
+
+    if !outcome.stdout.trim().is_empty() {
+        info!("Output of task \"{}\":\n{}", task_name, outcome.stdout.trim_end());
+    }
+
+    if !outcome.stderr.trim().is_empty() {
+        warn!("Error output of task \"{}\":\n{}", task_name, outcome.stderr.trim_end());
+    }
+}
+
+/// Retreives every task definition, from "tasks/tasks.yaml" and the folders of tasks with a "task.yaml" of their own.
+pub async fn task_definitions() -> Result<Value> {
+    load_task_definitions().await
+}
+
+/// Name a task is recorded under, as written in its definition, or as the user wrote it if no task answers to it.
+fn record_name(node: &TaskNode, context: &RunContext<'_>) -> String {
+    let label = task_label(&node.user_task, node.position);
+
+    match get_by_path(context.clean_task_input, &format!("{}/", label), None) {
+        Some((_, Value::String(a))) => original_task_name(context.task_input, &a).unwrap_or(label),
+        _ => label
+    }
+}
+
+/// Item variables a task is recorded with, so the copies made by "ForEach" and "Matrix" can be told apart.
+fn record_items(node: &TaskNode) -> serde_json::Map<String, serde_json::Value> {
+    node.bindings.iter()
+        .filter_map(|(name, value)| serde_json::to_value(value).ok().map(|a| (name.to_owned(), a)))
+        .collect()
+}
+
+/// Finds the task name as written in the task definitions from its sanitized form.
+pub fn original_task_name(task_input: &Value, sanitized: &str) -> Option<String> {
+    for key in task_input.as_mapping()?.keys() {
+        let key_str = match key.as_str() { Some(a) => a, None => continue };
+        if sanitize_string(key_str) == sanitized { return Some(key_str.to_owned()) }
+    }
+
+    None
 }
 
 /// Extracts the name of the nth key of a serde Mapping as a String.
 fn extract_map_nth_key(value: Value, index: usize) -> Option<String> {
-    let keys: Vec<&Value> = value.as_mapping()?.keys().into_iter().collect();
+    let keys: Vec<&Value> = value.as_mapping()?.keys().collect();
     let nth_key = keys.get(index)?;
-    return Some(nth_key.as_str()?.to_owned());
+    Some(nth_key.as_str()?.to_owned())
 }
 
 /// Returns a vector of defined task names.
 pub async fn get_task_str_sequence() -> Result<Vec<String>> {
-    let tasks_path = handle_logged_result(
-        get_task_script("tasks", Some("yaml")),
-        "Error occured when retreiving location of tasks folder: "
-    )?;
-
-    let tasks_file = handle_logged_result(
-        file_contents(&tasks_path).await, 
-        &format!("Error occured when reading file \"{}\": ", &tasks_path.display())
-    )?;
-
     let tasks_raw = handle_logged_result(
-        serde_yml::from_str(&tasks_file).map_err(|e| anyhow!(e.to_string())), 
-        "Error occured when parsing tasks file: "
+        task_definitions().await,
+        "Error occured when loading task definitions: "
     )?;
 
-    return match tasks_from_map(&tasks_raw) {
+    match tasks_from_map(&tasks_raw) {
         Some(tasks_vec) => {
             let mut tasks: Vec<String> = Vec::new();
             for task in tasks_vec {
@@ -477,7 +1541,112 @@ pub async fn get_task_str_sequence() -> Result<Vec<String>> {
         None => {
             let e = anyhow!("Could not retreive task definition list from task input Mapping.");
             error!("{}", e);
-            return Err(e)
+            Err(e)
         }
-    };
-}
\ No newline at end of file
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn exec_bash_script_is_interpolated() {
+        let task_input: Value = serde_yml::from_str(&std::fs::read_to_string("tasks/tasks.yaml").unwrap()).unwrap();
+        let user_input: Value = serde_yml::from_str(r#"
+            Variables:
+              greeting: hi
+            Tasks:
+              - ExecBash:
+                  Id: first
+                  Script: echo first
+              - ExecBash:
+                  ForEach: [a, b]
+                  Script: echo ${greeting} ${item} ${tasks.first.outputs.stdout} $${HOME}
+        "#).unwrap();
+
+        let (_, planned) = plan_tasks(&user_input, &task_input, &RunOptions::default()).await.unwrap();
+        let scripts: Vec<serde_json::Value> = planned.iter().map(|a| a.as_ref().unwrap().parameters["script"].clone()).collect();
+
+        // Outputs are only known once the task ran, so planning keeps them as written.
+        assert_eq!(scripts, vec![
+            "echo first",
+            "echo hi a ${tasks.first.outputs.stdout} ${HOME}",
+            "echo hi b ${tasks.first.outputs.stdout} ${HOME}"
+        ]);
+    }
+
+    /// Task definitions where "First" has an alias that is also the name of the task after it.
+    fn definitions() -> Value {
+        serde_yml::from_str(r#"
+            First:
+              +Aliases: [Second, One]
+              Param:
+                +Aliases: [Value]
+                +Data: 1
+            Second:
+              +Aliases: 2
+              Other: {}
+        "#).unwrap()
+    }
+
+    /// Key an entry was found under by *get_by_path()*, as text.
+    fn found_key(data: &Value, path: &str) -> Option<String> {
+        get_by_path(data, path, None).and_then(|(_, a)| a.as_str().map(|b| b.to_owned()))
+    }
+
+    #[test]
+    fn get_by_path_prefers_keys_over_aliases() {
+        let raw = definitions();
+        let clean = sanitize_yaml(&raw).unwrap();
+
+        for data in [&raw, &clean] {
+            assert_eq!(found_key(data, "second/").map(|a| sanitize_string(&a)), Some("second".to_owned()));
+            assert_eq!(found_key(data, "One/").map(|a| sanitize_string(&a)), Some("first".to_owned()));
+        }
+    }
+
+    #[test]
+    fn get_by_path_reads_raw_and_sanitized_data() {
+        let raw = definitions();
+        let clean = sanitize_yaml(&raw).unwrap();
+
+        assert_eq!(found_key(&raw, "FIRST/"), Some("First".to_owned()));
+        assert_eq!(found_key(&clean, "FIRST/"), Some("first".to_owned()));
+        assert_eq!(get_by_path(&raw, "One/Value/+Data", None).map(|(a, _)| a), Some(Value::from(1)));
+        assert_eq!(get_by_path(&clean, "One/Value/data", None).map(|(a, _)| a), Some(Value::from(1)));
+        assert_eq!(found_key(&raw, "Nope/"), None);
+    }
+
+    #[test]
+    fn get_by_path_skips_aliases_that_are_not_a_list() {
+        let raw = definitions();
+
+        // "Second" has "+Aliases: 2", which neither matches nor stops the lookup of the entries around it.
+        assert_eq!(found_key(&raw, "Second/Other/"), Some("Other".to_owned()));
+        assert_eq!(found_key(&raw, "One/"), Some("First".to_owned()));
+        assert_eq!(found_key(&raw, "2/"), None);
+    }
+
+    #[test]
+    fn zero_timeout_means_no_timeout() {
+        let task_data: Value = serde_yml::from_str("{timeout: 30m}").unwrap();
+        let timeout = |a: &str| task_timeout(&serde_yml::from_str(a).unwrap(), &task_data, "Task");
+
+        assert_eq!(timeout("{}"), Some(Duration::from_secs(1800)));
+        assert_eq!(timeout("{timeout: 90}"), Some(Duration::from_secs(90)));
+        assert_eq!(timeout("{timeout: 0}"), None);
+        assert_eq!(timeout("{timeout: '0s'}"), None);
+    }
+
+    #[tokio::test]
+    async fn undefined_variables_in_a_script_stop_the_run() {
+        let task_input: Value = serde_yml::from_str(&std::fs::read_to_string("tasks/tasks.yaml").unwrap()).unwrap();
+        let user_input: Value = serde_yml::from_str("Tasks: [{ExecBash: {Script: 'echo ${HOME}'}}]").unwrap();
+
+        let e = match plan_tasks(&user_input, &task_input, &RunOptions::default()).await {
+            Ok(_) => panic!("planned a script with an undefined variable"),
+            Err(e) => e.to_string()
+        };
+        assert!(e.contains("undefined variable \"HOME\""), "{}", e);
+    }
+}
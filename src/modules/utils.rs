@@ -1,11 +1,13 @@
 use anyhow::{Result, anyhow};
+use serde::{Deserialize, Deserializer, Serializer};
 use serde_yml::{Value, Sequence, Mapping};
 
 use std::path::PathBuf;
 use std::iter::zip;
+use std::time::Duration;
 
 use tokio::io::{BufWriter, AsyncWriteExt, AsyncReadExt};
-use tokio::fs::{create_dir, remove_file, File};
+use tokio::fs::{create_dir_all, remove_file, File};
 
 /// Strips numbers, symbols, and converts to lowercase on a string slice.
 pub fn sanitize_string(string: &str) -> String {
@@ -71,6 +73,42 @@ pub fn sanitize_yaml(input: &Value) -> Option<Value> {
     Some(input.clone())
 }
 
+/// Parses a duration such as "90", "90s", "15m" or "2h". Plain numbers are seconds.
+pub fn parse_duration(input: &str) -> Option<Duration> {
+    let trimmed = input.trim().to_ascii_lowercase();
+    let (number, multiplier) = match trimmed.chars().last()? {
+        's' => (&trimmed[..trimmed.len() - 1], 1.0),
+        'm' => (&trimmed[..trimmed.len() - 1], 60.0),
+        'h' => (&trimmed[..trimmed.len() - 1], 3600.0),
+        _ => (trimmed.as_str(), 1.0)
+    };
+
+    let seconds = number.trim().parse::<f64>().ok()? * multiplier;
+    if !seconds.is_finite() || seconds < 0.0 { return None }
+
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Parses a duration from a serde Value, either a number of seconds or a string accepted by *parse_duration()*.
+pub fn duration_from_value(value: &Value) -> Option<Duration> {
+    match value {
+        Value::Number(a) => parse_duration(&a.to_string()),
+        Value::String(a) => parse_duration(a),
+        _ => None
+    }
+}
+
+/// Serializes a Duration as a number of seconds, for use with *#[serde(serialize_with)]*.
+pub fn serialize_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// Deserializes a number of seconds as a Duration, for use with *#[serde(deserialize_with)]*.
+pub fn deserialize_secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let secs = f64::deserialize(deserializer)?;
+    Duration::try_from_secs_f64(secs).map_err(serde::de::Error::custom)
+}
+
 /// Creates a file with data at a given path.
 pub async fn create_file(data: &str, path: PathBuf) -> Result<()> {
     let file = File::create(path).await?;
@@ -105,10 +143,10 @@ pub async fn file_contents(path: &PathBuf) -> Result<String> {
     Ok(f_content)
 }
 
-/// Creates a given directory if it does not already exist.
+/// Creates a given directory, along with any missing parents, if it does not already exist.
 pub async fn ensure_dir(path: PathBuf) -> Result<()> {
-    if path.exists() { return Ok(()) }
-    create_dir(path).await?;
+    if path.as_os_str().is_empty() || path.exists() { return Ok(()) }
+    create_dir_all(path).await?;
 
     Ok(())
 }
@@ -125,4 +163,36 @@ pub fn handle_logged_result<T>(result: Result<T>, error_msg: &str) -> Result<T>
     }
 
     result
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_reads_units() {
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration(" 15M "), Some(Duration::from_secs(900)));
+        assert_eq!(parse_duration("2h"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("0"), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn parse_duration_rejects_bad_input() {
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("s"), None);
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(parse_duration("-5s"), None);
+        assert_eq!(parse_duration("NaN"), None);
+        assert_eq!(parse_duration("inf"), None);
+    }
+
+    #[test]
+    fn parse_duration_rejects_overflow() {
+        assert_eq!(parse_duration("1e30"), None);
+        assert_eq!(parse_duration("1e30h"), None);
+        assert_eq!(duration_from_value(&serde_yml::from_str("1e30").unwrap()), None);
+    }
+}
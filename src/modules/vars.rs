@@ -0,0 +1,310 @@
+use anyhow::{Result, anyhow};
+
+use serde_yml::{Mapping, Sequence, Value};
+
+use std::{
+    collections::HashMap,
+    env,
+    path::PathBuf
+};
+
+use crate::modules::utils::{file_contents, sanitize_string};
+
+/// Variables the tasks of a user file can reference as "${name}".
+///
+/// Names are matched ignoring case. "${env.NAME}" reads the environment variable NAME, other names never fall back to
+/// the environment, so "${HOME}" has to be written "${env.HOME}".
+#[derive(Debug, Clone, Default)]
+pub struct Variables {
+    values: HashMap<String, Value>
+}
+
+impl Variables {
+    /// Collects variables from every source. Later sources win: dotenv files in the order given, the "Variables"
+    /// block of the user's file, then "--var" arguments.
+    ///
+    /// Values in the "Variables" block may themselves use "${...}" to reference the other sources.
+    pub async fn load(user_input: &Value, env_files: &[PathBuf], overrides: &[(String, String)]) -> Result<Variables> {
+        let mut outer = Variables::default();
+
+        for path in env_files {
+            let contents = file_contents(path).await
+                .map_err(|e| anyhow!("Could not read the dotenv file \"{}\": {}", path.display(), e))?;
+            for (name, value) in parse_dotenv(&contents).map_err(|e| anyhow!("Could not read the dotenv file \"{}\": {}", path.display(), e))? {
+                outer.set(&name, Value::String(value));
+            }
+        }
+
+        for (name, value) in overrides {
+            outer.set(name, parse_var_value(value));
+        }
+
+        let block = user_input.as_mapping()
+            .and_then(|a| a.iter().find(|(b, _)| b.as_str().is_some_and(|c| sanitize_string(c) == "variables")))
+            .map(|(_, b)| b.to_owned());
+
+        let mut variables = Variables::default();
+        match block {
+            None | Some(Value::Null) => {},
+            Some(Value::Mapping(a)) => {
+                let lookup = |name: &str| outer.get(name);
+                for (name, value) in &a {
+                    let name = name.as_str().ok_or(anyhow!("Variable names have to be text, not {:?}.", name))?;
+                    let value = interpolate(value, &lookup, "")
+                        .map_err(|e| anyhow!("Variable \"{}\" could not be set: {}", name, e))?;
+                    variables.set(name, value);
+                }
+            },
+            Some(a) => return Err(anyhow!("\"Variables\" should be a mapping of names to values, not {:?}.", a))
+        }
+
+        // "--var" wins over the file, the file wins over dotenv files.
+        for (name, value) in outer.values {
+            let file_has_it = variables.values.contains_key(&name);
+            let is_override = overrides.iter().any(|(a, _)| a.to_lowercase() == name);
+            if is_override || !file_has_it { variables.values.insert(name, value); }
+        }
+
+        Ok(variables)
+    }
+
+    fn set(&mut self, name: &str, value: Value) {
+        self.values.insert(name.to_lowercase(), value);
+    }
+
+    /// Every variable as a map sorted by name, e.g. to tell whether the variables of two runs are the same.
+    pub fn to_value(&self) -> Value {
+        let mut names: Vec<&String> = self.values.keys().collect();
+        names.sort();
+
+        Value::Mapping(names.into_iter().map(|a| (Value::String(a.to_owned()), self.values[a].to_owned())).collect())
+    }
+
+    /// Looks up a variable. "env.NAME" looks in the environment instead.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        if let Some(a) = name.strip_prefix("env.") {
+            return env::var(a).ok().map(Value::String)
+        }
+
+        self.values.get(&name.to_lowercase()).cloned()
+    }
+}
+
+/// Reads a "--var" value as YAML, so "--var elevated=true" gives a bool. Anything that isn't valid YAML stays text.
+fn parse_var_value(value: &str) -> Value {
+    serde_yml::from_str::<Value>(value).unwrap_or(Value::String(value.to_owned()))
+}
+
+/// Reads the "NAME=value" lines of a dotenv file. Blank lines and lines starting with "#" are ignored, as is an
+/// "export " in front of the name. Values may be wrapped in single or double quotes.
+fn parse_dotenv(contents: &str) -> Result<Vec<(String, String)>> {
+    let mut pairs = Vec::new();
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (name, value) = line.split_once('=')
+            .ok_or(anyhow!("line {} should look like NAME=value.", number + 1))?;
+
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(anyhow!("line {} has no name before the \"=\".", number + 1))
+        }
+
+        let value = value.trim();
+        let unquoted = [('"', '"'), ('\'', '\'')].iter()
+            .find_map(|(a, b)| value.strip_prefix(*a).and_then(|c| c.strip_suffix(*b)))
+            .unwrap_or(value);
+
+        pairs.push((name.to_owned(), unquoted.to_owned()));
+    }
+
+    Ok(pairs)
+}
+
+/// Replaces "${name}" references in every string of a value, looking each name up with *lookup*.
+/// "$${" is kept as a literal "${".
+///
+/// A string that is nothing but a single reference is replaced by the referenced value as is, so lists and maps
+/// can be passed on whole. Inside longer text, only text, numbers and true/false can be used.
+///
+/// *path* is where the value sits, e.g. "drives/y", and is used in error messages.
+pub fn interpolate(value: &Value, lookup: &dyn Fn(&str) -> Option<Value>, path: &str) -> Result<Value> {
+    let join = |key: &str| if path.is_empty() { key.to_owned() } else { format!("{}/{}", path, key) };
+
+    match value {
+        Value::String(a) => interpolate_str(a, lookup).map_err(|e| match path.is_empty() {
+            true => e,
+            false => anyhow!("{} in parameter \"{}\"", e, path)
+        }),
+        Value::Sequence(a) => {
+            let mut seq = Sequence::new();
+            for (i, b) in a.iter().enumerate() { seq.push(interpolate(b, lookup, &join(&i.to_string()))?) }
+            Ok(Value::Sequence(seq))
+        },
+        Value::Mapping(a) => {
+            let mut map = Mapping::new();
+            for (k, v) in a {
+                let key = k.as_str().map(|b| b.to_owned()).unwrap_or(format!("{:?}", k));
+                map.insert(k.to_owned(), interpolate(v, lookup, &join(&key))?);
+            }
+            Ok(Value::Mapping(map))
+        },
+        a => Ok(a.to_owned())
+    }
+}
+
+/// Replaces the "${name}" references of a single string, see *interpolate()*.
+fn interpolate_str(input: &str, lookup: &dyn Fn(&str) -> Option<Value>) -> Result<Value> {
+    let trimmed = input.trim();
+    if let Some(name) = trimmed.strip_prefix("${").and_then(|a| a.strip_suffix('}'))
+        && !name.contains('}')
+        && !name.contains("${") {
+        let name = name.trim();
+        return lookup(name).ok_or(undefined(name))
+    }
+
+    let mut output = String::new();
+    let mut rest = input;
+
+    while let Some(start) = rest.find("${") {
+        // "$${" escapes a reference
+        if rest[..start].ends_with('$') {
+            output.push_str(&rest[..start - 1]);
+            output.push_str("${");
+            rest = &rest[start + 2..];
+            continue
+        }
+
+        output.push_str(&rest[..start]);
+        let end = rest[start..].find('}')
+            .ok_or(anyhow!("\"${{\" is missing its closing \"}}\""))?;
+        let name = rest[start + 2..start + end].trim();
+
+        let text = match lookup(name) {
+            Some(Value::String(a)) => a,
+            Some(Value::Number(a)) => a.to_string(),
+            Some(Value::Bool(a)) => a.to_string(),
+            Some(Value::Null) => String::new(),
+            Some(_) => return Err(anyhow!("variable \"{}\" is a list or map, so it can only be used on its own, as \"${{{}}}\"", name, name)),
+            None => return Err(undefined(name))
+        };
+
+        output.push_str(&text);
+        rest = &rest[start + end + 1..];
+    }
+
+    output.push_str(rest);
+    Ok(Value::String(output))
+}
+
+/// Error for a "${name}" reference that could not be resolved.
+fn undefined(name: &str) -> anyhow::Error {
+    match name.strip_prefix("tasks.").and_then(|a| a.split_once(".outputs.")) {
+        Some((id, output)) => anyhow!("task \"{}\" did not output \"{}\"", id, output),
+        None => anyhow!("undefined variable \"{}\" (write \"$${{{}}}\" to pass it on as written)", name, name)
+    }
+}
+
+/// Returns the Ids of the tasks whose outputs are referenced as "${tasks.<id>.outputs.<name>}" anywhere in a value.
+pub fn task_output_refs(value: &Value) -> Vec<String> {
+    fn collect(value: &Value, ids: &mut Vec<String>) {
+        match value {
+            Value::String(a) => {
+                let mut rest = a.as_str();
+                while let Some(start) = rest.find("${") {
+                    let end = match rest[start..].find('}') { Some(b) => start + b, None => break };
+                    let escaped = rest[..start].ends_with('$');
+                    let name = rest[start + 2..end].trim();
+                    rest = &rest[end + 1..];
+                    if escaped { continue }
+
+                    if let Some((id, _)) = name.strip_prefix("tasks.").and_then(|b| b.split_once('.'))
+                        && !ids.iter().any(|b| b == id) {
+                        ids.push(id.to_owned());
+                    }
+                }
+            },
+            Value::Sequence(a) => for b in a { collect(b, ids) },
+            Value::Mapping(a) => for b in a.values() { collect(b, ids) },
+            _ => {}
+        }
+    }
+
+    let mut ids = Vec::new();
+    collect(value, &mut ids);
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<Value> {
+        match name {
+            "name" => Some(Value::from("world")),
+            "count" => Some(Value::from(3)),
+            "list" => Some(serde_yml::from_str("[a, b]").unwrap()),
+            _ => None
+        }
+    }
+
+    #[test]
+    fn dotenv_lines() {
+        let pairs = parse_dotenv("# comment\n\nexport NAME=world\nQUOTED = \"a b\"\nSINGLE='c=d'\nEMPTY=\n").unwrap();
+        assert_eq!(pairs, vec![
+            ("NAME".to_owned(), "world".to_owned()),
+            ("QUOTED".to_owned(), "a b".to_owned()),
+            ("SINGLE".to_owned(), "c=d".to_owned()),
+            ("EMPTY".to_owned(), String::new())
+        ]);
+
+        assert_eq!(parse_dotenv("A=1\nnonsense").unwrap_err().to_string(), "line 2 should look like NAME=value.");
+        assert_eq!(parse_dotenv("=1").unwrap_err().to_string(), "line 1 has no name before the \"=\".");
+    }
+
+    #[test]
+    fn references_in_text() {
+        assert_eq!(interpolate_str("hello ${name}, ${ count } times", &lookup).unwrap(), Value::from("hello world, 3 times"));
+        assert_eq!(interpolate_str("echo $${HOME} ${name}", &lookup).unwrap(), Value::from("echo ${HOME} world"));
+        assert_eq!(interpolate_str("$${name}", &lookup).unwrap(), Value::from("${name}"));
+
+        let e = interpolate_str("echo ${HOME}", &lookup).unwrap_err();
+        assert_eq!(e.to_string(), "undefined variable \"HOME\" (write \"$${HOME}\" to pass it on as written)");
+        assert!(interpolate_str("items: ${list}", &lookup).is_err());
+        assert!(interpolate_str("${name", &lookup).is_err());
+    }
+
+    #[test]
+    fn whole_string_references_keep_their_value() {
+        assert_eq!(interpolate_str("${count}", &lookup).unwrap(), Value::from(3));
+        assert_eq!(interpolate_str(" ${list} ", &lookup).unwrap(), lookup("list").unwrap());
+
+        let value: Value = serde_yml::from_str("{hosts: '${list}', greeting: 'hi ${name}', port: 80}").unwrap();
+        let expected: Value = serde_yml::from_str("{hosts: [a, b], greeting: hi world, port: 80}").unwrap();
+        assert_eq!(interpolate(&value, &lookup, "").unwrap(), expected);
+
+        let value: Value = serde_yml::from_str("{drives: {y: '${nope}'}}").unwrap();
+        assert!(interpolate(&value, &lookup, "").unwrap_err().to_string().ends_with("in parameter \"drives/y\""));
+    }
+
+    #[tokio::test]
+    async fn var_beats_the_file_which_beats_dotenv() {
+        let path = env::temp_dir().join(format!("tm-vars-{}.env", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "A=dotenv\nB=dotenv\nC=dotenv\n").unwrap();
+
+        let user_input: Value = serde_yml::from_str("Variables: {b: file, c: file, d: '${a}-${C}'}").unwrap();
+        let overrides = vec![("C".to_owned(), "var".to_owned())];
+        let variables = Variables::load(&user_input, std::slice::from_ref(&path), &overrides).await.unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(variables.get("a"), Some(Value::from("dotenv")));
+        assert_eq!(variables.get("B"), Some(Value::from("file")));
+        assert_eq!(variables.get("c"), Some(Value::from("var")));
+        assert_eq!(variables.get("d"), Some(Value::from("dotenv-var")));
+        assert_eq!(variables.get("path"), None);
+        assert_eq!(variables.get("env.PATH"), env::var("PATH").ok().map(Value::String));
+    }
+}
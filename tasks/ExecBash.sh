@@ -0,0 +1,27 @@
+source ./tasks/lib/utils.sh
+
+New-TaskReport
+
+script="$(Get-TaskArgs "$taskData" script)"
+
+stdout_path="./.tm_temp/STDOUT-$taskId.txt"
+stderr_path="./.tm_temp/STDERR-$taskId.txt"
+
+Send-TaskProgress "$taskId" "" "Running script"
+bash -c "$script" > "$stdout_path" 2> "$stderr_path"
+exit_code=$?
+
+if [[ $exit_code -eq 0 ]]; then
+    Add-ReportMessage info "The following script executed successfully:"$'\n'"$script"
+else
+    Add-ReportMessage error "The following script failed with exit code $exit_code:"$'\n'"$script"
+    Set-ReportStatus failure
+fi
+
+Add-ReportMessage info "STDOUT:"$'\n'"$(cat "$stdout_path")"
+Add-ReportMessage warn "STDERR:"$'\n'"$(cat "$stderr_path")"
+Set-ReportOutput stdout "$(cat "$stdout_path")"
+Sync-Report "$taskId"
+
+rm -f "$stdout_path" "$stderr_path"
+exit $exit_code
@@ -1,8 +1,7 @@
-
 Import-Module -Force .\tasks\lib\utils.psm1
 $taskArgs = Get-TaskArgs -Data $taskData
 
-$report = @()
+$report = New-TaskReport
 
 $script = $taskArgs."script"
 
@@ -14,18 +13,26 @@ $script | Out-File -FilePath $scriptPath
 Start-Sleep -Seconds 1
 
 try {
-    Start-Process -FilePath "powershell.exe" -ArgumentList "-File $scriptPath" -RedirectStandardOutput $stdoutPath -RedirectStandardError $stderrPath -Wait -PassThru -NoNewWindow
+    $proc = Start-Process -FilePath "powershell.exe" -ArgumentList "-File $scriptPath" -RedirectStandardOutput $stdoutPath -RedirectStandardError $stderrPath -Wait -PassThru -NoNewWindow
+    if ($proc.ExitCode -eq 0) {
+        Add-ReportMessage -Report $report -Text "The following script executed successfully:`n$($taskArgs."script")"
+    }
+    else {
+        Add-ReportMessage -Report $report -Level "error" -Text "The following script exited with code $($proc.ExitCode):`n$($taskArgs."script")"
+        $report.status = "failure"
+    }
 }
 catch {
     Write-Error "An error occurred: $($_.Exception.Message)"
-    $report += "The following script failed with `"$($_.Exception.Message)`":`n$($taskArgs."script")"
+    Add-ReportMessage -Report $report -Level "error" -Text "The following script failed with `"$($_.Exception.Message)`":`n$($taskArgs."script")"
+    $report.status = "failure"
 }
 
-$report += "STDOUT:`n$(Get-Content -Path $stdoutPath)"
-$report += "STDERR:`n$(Get-Content -Path $stderrPath)"
-$report += "The following script executed successfully:`n$($taskArgs."script")"
-Sync-Report -Report $report -TaskID $taskId
+Add-ReportMessage -Report $report -Text "STDOUT:`n$(Get-Content -Path $stdoutPath)"
+Add-ReportMessage -Report $report -Level "warn" -Text "STDERR:`n$(Get-Content -Path $stderrPath)"
+$report.outputs["stdout"] = "$(Get-Content -Path $stdoutPath -Raw)".TrimEnd()
+Sync-Report -Report $report -TaskID $taskId -Uri $reportUri -Token $reportToken
 
 Remove-Item -Path $scriptPath
 Remove-Item -Path $stdoutPath
-Remove-Item -Path $stderrPath
\ No newline at end of file
+Remove-Item -Path $stderrPath
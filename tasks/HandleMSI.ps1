@@ -0,0 +1,46 @@
+Import-Module -Force .\tasks\lib\utils.psm1
+$taskArgs = Get-TaskArgs -Data $taskData
+
+$report = New-TaskReport
+
+$stdoutPath = ".\.tm_temp\STDOUT-$taskId.ps1"
+$stderrPath = ".\.tm_temp\STDERR-$taskId.ps1"
+
+$msi = $taskArgs."msi"
+$action = if ($taskArgs."uninstall") { "Uninstalling" } else { "Installing" }
+
+# msiexec takes the package after /i or /x, then the switches and properties
+$msiArgs = @(if ($taskArgs."uninstall") { "/x" } else { "/i" }, "`"$msi`"")
+if ($taskArgs."silent") { $msiArgs += "/qn" }
+if ($taskArgs."args") { $msiArgs += $taskArgs."args" }
+
+Send-TaskProgress -Status "$action $msi" -TaskID $taskId -Uri $reportUri -Token $reportToken
+
+try {
+    $proc = Start-Process -FilePath "msiexec.exe" -ArgumentList $msiArgs -RedirectStandardOutput $stdoutPath -RedirectStandardError $stderrPath -Wait -PassThru -NoNewWindow
+
+    # 3010 means it worked, but the computer has to restart to finish
+    if ($proc.ExitCode -eq 0 -or $proc.ExitCode -eq 3010) {
+        Add-ReportMessage -Report $report -Text "$action the following MSI succeeded:`n$msi"
+        $report.changed += $msi
+        if ($proc.ExitCode -eq 3010) {
+            Add-ReportMessage -Report $report -Level "warn" -Text "A restart is required to finish $($action.ToLower()) $msi."
+        }
+    }
+    else {
+        Add-ReportMessage -Report $report -Level "error" -Text "$action the following MSI exited with code $($proc.ExitCode):`n$msi"
+        $report.status = "failure"
+    }
+}
+catch {
+    Write-Error "An error occurred: $($_.Exception.Message)"
+    Add-ReportMessage -Report $report -Level "error" -Text "$action the following MSI failed with `"$($_.Exception.Message)`":`n$msi"
+    $report.status = "failure"
+}
+
+Add-ReportMessage -Report $report -Text "STDOUT:`n$(Get-Content -Path $stdoutPath)"
+Add-ReportMessage -Report $report -Level "warn" -Text "STDERR:`n$(Get-Content -Path $stderrPath)"
+Sync-Report -Report $report -TaskID $taskId -Uri $reportUri -Token $reportToken
+
+Remove-Item -Path $stdoutPath
+Remove-Item -Path $stderrPath
@@ -1,29 +1,33 @@
-
 Import-Module -Force .\tasks\lib\utils.psm1
 $taskArgs = Get-TaskArgs -Data $taskData
 
-$report = @()
+$report = New-TaskReport
 
-$scriptPath = ".\.tm_temp\$($taskId).ps1"
 $stdoutPath = ".\.tm_temp\STDOUT-$taskId.ps1"
 $stderrPath = ".\.tm_temp\STDERR-$taskId.ps1"
 
-$script | Out-File -FilePath $scriptPath
-Start-Sleep -Seconds 1
+Send-TaskProgress -Status "Running $($taskArgs."exe")" -TaskID $taskId -Uri $reportUri -Token $reportToken
 
 try {
-    Start-Process -FilePath $taskArgs."exe" -ArgumentList $taskArgs."args" -RedirectStandardOutput $stdoutPath -RedirectStandardError $stderrPath -Wait -PassThru -NoNewWindow
+    $proc = Start-Process -FilePath $taskArgs."exe" -ArgumentList $taskArgs."args" -RedirectStandardOutput $stdoutPath -RedirectStandardError $stderrPath -Wait -PassThru -NoNewWindow
+    if ($proc.ExitCode -eq 0) {
+        Add-ReportMessage -Report $report -Text "The following program executed successfully:`n$($taskArgs."exe")"
+        $report.changed += $taskArgs."exe"
+    }
+    else {
+        Add-ReportMessage -Report $report -Level "error" -Text "The following program exited with code $($proc.ExitCode):`n$($taskArgs."exe")"
+        $report.status = "failure"
+    }
 }
 catch {
     Write-Error "An error occurred: $($_.Exception.Message)"
-    $report += "The following program failed with `"$($_.Exception.Message)`":`n$($taskArgs."exe")"
+    Add-ReportMessage -Report $report -Level "error" -Text "The following program failed with `"$($_.Exception.Message)`":`n$($taskArgs."exe")"
+    $report.status = "failure"
 }
 
-$report += "STDOUT:`n$(Get-Content -Path $stdoutPath)"
-$report += "STDERR:`n$(Get-Content -Path $stderrPath)"
-$report += "The following program executed successfully:`n$($taskArgs."exe")"
-Sync-Report -Report $report -TaskID $taskId
+Add-ReportMessage -Report $report -Text "STDOUT:`n$(Get-Content -Path $stdoutPath)"
+Add-ReportMessage -Report $report -Level "warn" -Text "STDERR:`n$(Get-Content -Path $stderrPath)"
+Sync-Report -Report $report -TaskID $taskId -Uri $reportUri -Token $reportToken
 
-Remove-Item -Path $scriptPath
 Remove-Item -Path $stdoutPath
-Remove-Item -Path $stderrPath
\ No newline at end of file
+Remove-Item -Path $stderrPath
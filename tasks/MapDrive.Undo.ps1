@@ -0,0 +1,23 @@
+Import-Module -Force .\tasks\lib\utils.psm1
+$taskArgs = Get-TaskArgs -Data $taskData
+
+$report = New-TaskReport
+
+foreach ($letter in $taskArgs."drives".Keys) {
+    $name = $letter.ToUpper().TrimEnd(":")
+
+    try {
+        Remove-PSDrive -Name $name -PSProvider "FileSystem" -Scope Global -Force -ErrorAction Stop
+    }
+    catch {
+        Write-Error "An error occurred: $($_.Exception.Message)"
+        Add-ReportMessage -Report $report -Level "error" -Text "Attempted to unmap drive letter $name, failed with `"$($_.Exception.Message)`""
+        $report.status = "failure"
+        continue
+    }
+
+    Add-ReportMessage -Report $report -Text "Unmapped drive letter $name."
+    $report.changed += "$($name): unmapped"
+}
+
+Sync-Report -Report $report -TaskID $taskId -Uri $reportUri -Token $reportToken
@@ -1,22 +1,29 @@
-
 Import-Module -Force .\tasks\lib\utils.psm1
 $taskArgs = Get-TaskArgs -Data $taskData
 
-$report = @()
+$report = New-TaskReport
+
+$driveCount = $taskArgs."drives".Keys.Count
+$driveIndex = 0
 
 foreach ($letter in $taskArgs."drives".Keys) {
     $path = $taskArgs."drives"[$letter]
+    $driveIndex += 1
+    Send-TaskProgress -Percent ([int](($driveIndex - 1) / $driveCount * 100)) -Status "Mapping drive $letter to $path" -TaskID $taskId -Uri $reportUri -Token $reportToken
+
     try {
         New-PSDrive -Name $letter.ToUpper() -PSProvider "FileSystem" -Root $path -Persist -Scope Global -ErrorAction Stop
     }
     catch {
         Write-Error "An error occurred: $($_.Exception.Message)"
-        $report += "Attempted to map drive letter $letter to path $path, failed with `"$($_.Exception.Message)`""
+        Send-TaskLog -Level "error" -Message "Could not map drive $letter to $($path): $($_.Exception.Message)" -TaskID $taskId -Uri $reportUri -Token $reportToken
+        Add-ReportMessage -Report $report -Level "error" -Text "Attempted to map drive letter $letter to path $path, failed with `"$($_.Exception.Message)`""
+        $report.status = "failure"
         continue
     }
 
-    $actionStr = "Mapped drive letter $letter to path $path."
-    $report += $actionStr
+    Add-ReportMessage -Report $report -Text "Mapped drive letter $letter to path $path."
+    $report.changed += "$($letter.ToUpper()): -> $path"
 }
 
-Sync-Report -Report $report -TaskID $taskId
\ No newline at end of file
+Sync-Report -Report $report -TaskID $taskId -Uri $reportUri -Token $reportToken
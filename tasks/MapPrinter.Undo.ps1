@@ -0,0 +1,21 @@
+Import-Module -Force .\tasks\lib\utils.psm1
+$taskArgs = Get-TaskArgs -Data $taskData
+
+$report = New-TaskReport
+
+foreach ($printer in $taskArgs."printers") {
+    try {
+        Remove-Printer -Name "\\$printer" -ErrorAction Stop
+    }
+    catch {
+        Write-Error "An error occurred: $($_.Exception.Message)"
+        Add-ReportMessage -Report $report -Level "error" -Text (Add-Timestamp -InputString "Attempted to remove printer $printer, failed with $($_.Exception.Message)")
+        $report.status = "failure"
+        continue
+    }
+
+    Add-ReportMessage -Report $report -Text (Add-Timestamp -InputString "Removed printer $printer.")
+    $report.changed += $printer
+}
+
+Sync-Report -Report $report -TaskID $taskId -Uri $reportUri -Token $reportToken
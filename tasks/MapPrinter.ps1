@@ -1,8 +1,7 @@
-
 Import-Module -Force .\tasks\lib\utils.psm1
 $taskArgs = Get-TaskArgs -Data $taskData
 
-$report = @()
+$report = New-TaskReport
 
 foreach ($printer in $taskArgs."printers") {
     try {
@@ -10,11 +9,13 @@ foreach ($printer in $taskArgs."printers") {
     }
     catch {
         Write-Error "An error occurred: $($_.Exception.Message)"
-        $report += (Add-Timestamp -InputString "Attempted to add printer $printer, failed with $($_.Exception.Message)")
+        Add-ReportMessage -Report $report -Level "error" -Text (Add-Timestamp -InputString "Attempted to add printer $printer, failed with $($_.Exception.Message)")
+        $report.status = "failure"
         continue
     }
 
-    $report += (Add-Timestamp -InputString "Added printer $printer.")
+    Add-ReportMessage -Report $report -Text (Add-Timestamp -InputString "Added printer $printer.")
+    $report.changed += $printer
 }
 
-Sync-Report -Report $report -TaskID $taskId
\ No newline at end of file
+Sync-Report -Report $report -TaskID $taskId -Uri $reportUri -Token $reportToken
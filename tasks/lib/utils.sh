@@ -0,0 +1,199 @@
+#!/usr/bin/env bash
+# Bash equivalent of utils.psm1. Source it from a task script with:
+#   source ./tasks/lib/utils.sh
+#
+# JSON is handled with jq when it is installed, and python3 otherwise.
+
+function NormalizeString {
+    local string="${1,,}"
+    printf '%s' "${string//[^a-z]/}"
+}
+
+function ConvertTo-JsonString {
+    local string="$1"
+    string="${string//\\/\\\\}"
+    string="${string//\"/\\\"}"
+    string="${string//$'\n'/\\n}"
+    string="${string//$'\r'/\\r}"
+    string="${string//$'\t'/\\t}"
+    printf '"%s"' "$string"
+}
+
+function ConvertTo-JsonArray {
+    local output="["
+    local separator=""
+
+    for item in "$@"; do
+        output+="$separator$(ConvertTo-JsonString "$item")"
+        separator=","
+    done
+
+    printf '%s]' "$output"
+}
+
+# Prints the value at a dotted path of the task data, e.g. `Get-TaskArgs "$taskData" drives`.
+# Strings are printed raw, lists of plain values one item per line, and anything else as JSON.
+function Get-TaskArgs {
+    local data="$1"
+    local path="${2:-}"
+
+    if command -v jq > /dev/null 2>&1; then
+        jq -r --arg p "$path" '
+            getpath($p | split(".") | map(select(. != "")))
+            | if type == "string" then .
+              elif type == "array" and all(.[]; type != "array" and type != "object") then .[]
+              else tojson end
+        ' <<< "$data"
+        return $?
+    fi
+
+    python3 - "$data" "$path" << 'EOF'
+import json, sys
+
+value = json.loads(sys.argv[1])
+for key in [k for k in sys.argv[2].split(".") if k]:
+    value = value[int(key)] if isinstance(value, list) else value[key]
+
+if isinstance(value, str):
+    print(value)
+elif isinstance(value, list) and all(not isinstance(v, (list, dict)) for v in value):
+    for v in value:
+        print(v if isinstance(v, str) else json.dumps(v))
+else:
+    print(json.dumps(value))
+EOF
+}
+
+function Add-Timestamp {
+    local input="$1"
+    local include_username="${2:-true}"
+
+    if [[ "$include_username" == "true" ]]; then
+        input="${USER:-$(id -un)} | $input"
+    fi
+
+    printf '%s | %s' "$(date '+%m-%d-%Y %H:%M:%S')" "$input"
+}
+
+# Posts a JSON body to a path of the report server, using the `reportUri` and `reportToken` set by the script preamble.
+function Invoke-TaskPost {
+    local path="$1"
+    local body="$2"
+
+    curl -s -X POST \
+        -H "Content-Type: application/json" \
+        -H "Authorization: Bearer $reportToken" \
+        --data "$body" \
+        "$reportUri/$path"
+}
+
+# Starts a fresh report. The Add-/Set-Report* functions build it up and Sync-Report sends it.
+function New-TaskReport {
+    reportStatus="success"
+    reportMessages=()
+    reportChanged=()
+    reportUnchanged=()
+    reportArtifacts=()
+    unset reportOutputs
+    declare -gA reportOutputs=()
+}
+
+# Sets the overall status of the report: success, warning or failure.
+function Set-ReportStatus {
+    reportStatus="$1"
+}
+
+# Adds a leveled message to the report, e.g. `Add-ReportMessage error "Could not copy file"`.
+function Add-ReportMessage {
+    local level="$1"
+    local text="$2"
+
+    reportMessages+=("{\"level\":$(ConvertTo-JsonString "$level"),\"text\":$(ConvertTo-JsonString "$text")}")
+}
+
+# Records something the task changed.
+function Add-ReportChange {
+    reportChanged+=("$1")
+}
+
+# Records something the task found already in the desired state.
+function Add-ReportUnchanged {
+    reportUnchanged+=("$1")
+}
+
+# Sets a named output of the task, e.g. `Set-ReportOutput installPath "/opt/example"`.
+function Set-ReportOutput {
+    reportOutputs["$1"]="$2"
+}
+
+# Records the path of a file the task produced.
+function Add-ReportArtifact {
+    reportArtifacts+=("$1")
+}
+
+# Sends the report built since New-TaskReport to Taskmaster, e.g. `Sync-Report "$taskId"`.
+# Pass "show-response" after the task ID to print the server's response on stderr.
+function Sync-Report {
+    local task_id="$1"
+
+    local messages="["
+    local separator=""
+    for message in "${reportMessages[@]}"; do
+        messages+="$separator$message"
+        separator=","
+    done
+
+    local outputs="{"
+    separator=""
+    for name in "${!reportOutputs[@]}"; do
+        outputs+="$separator$(ConvertTo-JsonString "$name"):$(ConvertTo-JsonString "${reportOutputs[$name]}")"
+        separator=","
+    done
+
+    local body="{\"version\":1"
+    body+=",\"status\":$(ConvertTo-JsonString "$reportStatus")"
+    body+=",\"messages\":$messages]"
+    body+=",\"changed\":$(ConvertTo-JsonArray "${reportChanged[@]}")"
+    body+=",\"unchanged\":$(ConvertTo-JsonArray "${reportUnchanged[@]}")"
+    body+=",\"outputs\":$outputs}"
+    body+=",\"artifacts\":$(ConvertTo-JsonArray "${reportArtifacts[@]}")}"
+
+    local response
+    response="$(Invoke-TaskPost "$task_id" "$body")"
+
+    if [[ "${2:-}" == "show-response" ]]; then
+        echo "Server response: $response" >&2
+    fi
+}
+
+# Shows progress while the task runs, e.g. `Send-TaskProgress "$taskId" 40 "Copying files"`.
+# Either the percentage or the status may be left empty.
+function Send-TaskProgress {
+    local task_id="$1"
+    local percent="${2:-}"
+    local status="${3:-}"
+
+    local body="{"
+    local separator=""
+
+    if [[ -n "$percent" ]]; then
+        body+="\"percent\":$percent"
+        separator=","
+    fi
+
+    if [[ -n "$status" ]]; then
+        body+="$separator\"status\":$(ConvertTo-JsonString "$status")"
+    fi
+
+    Invoke-TaskPost "$task_id/progress" "$body}" > /dev/null
+}
+
+# Logs a line while the task runs, e.g. `Send-TaskLog "$taskId" warn "Retrying download"`.
+# Levels are trace, debug, info, warn and error.
+function Send-TaskLog {
+    local task_id="$1"
+    local level="$2"
+    local message="$3"
+
+    Invoke-TaskPost "$task_id/log" "{\"level\":$(ConvertTo-JsonString "$level"),\"message\":$(ConvertTo-JsonString "$message")}" > /dev/null
+}